@@ -0,0 +1,381 @@
+use crate::tracts::{Strand, Tract};
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+/// A component line of an AGP file, a piece of a contig placed
+/// in a scaffold. Coordinates are 0-based and half open.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Component {
+    /// The scaffold.
+    pub object: String,
+    pub object_start: usize,
+    pub object_end: usize,
+    /// The contig.
+    pub component: String,
+    pub component_start: usize,
+    pub component_end: usize,
+    /// Is the contig reverse complemented in the scaffold?
+    pub reversed: bool,
+}
+
+/// Where a tract ends up in its scaffold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Position {
+    /// At an end of the scaffold.
+    End,
+    /// At an end of its contig, but inside the scaffold, which
+    /// is usually a curation mistake.
+    Internal,
+    /// Away from the ends of both the contig and the scaffold.
+    Interstitial,
+}
+
+impl Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Position::End => write!(f, "end"),
+            Position::Internal => write!(f, "internal"),
+            Position::Interstitial => write!(f, "interstitial"),
+        }
+    }
+}
+
+/// A tract in the other coordinate system of an AGP: on the scaffold if
+/// it was found on a contig, and on the contig if it was found on a
+/// scaffold.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LiftedTract {
+    pub id: String,
+    pub start: usize,
+    pub end: usize,
+    pub strand: Strand,
+    pub position: Position,
+}
+
+/// The header of the tract BED file with an AGP, which has the lifted
+/// tract as extra columns.
+pub const LIFTED_TRACTS_HEADER: &str = "#id\tstart\tend\ttelomeric_repeat\tscore\tstrand\tunits\tpurity\tlifted_id\tlifted_start\tlifted_end\tlifted_strand\tposition";
+
+/// Format a lifted tract as the extra BED columns, or `NA` if the
+/// tract couldn't be lifted.
+pub fn lifted_columns(lifted: Option<&LiftedTract>) -> String {
+    match lifted {
+        Some(l) => format!(
+            "{}\t{}\t{}\t{}\t{}",
+            l.id, l.start, l.end, l.strand, l.position
+        ),
+        None => ".\tNA\tNA\t.\tNA".into(),
+    }
+}
+
+/// The layout of scaffolds from contigs, from an AGP (version 2.x) file.
+#[derive(Debug, Default)]
+pub struct Agp {
+    components: Vec<Component>,
+    /// The components of each scaffold, and the pieces of each contig.
+    by_object: HashMap<String, Vec<usize>>,
+    by_component: HashMap<String, Vec<usize>>,
+    /// The length of each scaffold, including its gaps.
+    object_lengths: HashMap<String, usize>,
+    /// The furthest any piece of each contig reaches, which is its length
+    /// unless its end was left out of the scaffolds.
+    component_lengths: HashMap<String, usize>,
+}
+
+impl Agp {
+    /// Read the AGP file at `path`.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file =
+            File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        Agp::parse(BufReader::new(file))
+            .with_context(|| format!("Failed to read the AGP {}", path.display()))
+    }
+
+    /// Read the lines of an AGP. Comments and blank lines are skipped,
+    /// and gap lines only count towards the length of their scaffold.
+    pub fn parse<R: BufRead>(reader: R) -> Result<Self> {
+        let mut agp = Agp::default();
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.trim_end().split('\t').collect();
+            if fields.len() < 8 {
+                bail!("The AGP line {:?} has fewer than eight columns", line)
+            }
+            let position = |field: &str| -> Result<usize> {
+                field
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|p| *p > 0)
+                    .with_context(|| format!("{:?} is not a position, in {:?}", field, line))
+            };
+            let object = fields[0].to_string();
+            let object_start = position(fields[1])? - 1;
+            let object_end = position(fields[2])?;
+            let length = agp.object_lengths.entry(object.clone()).or_insert(0);
+            *length = (*length).max(object_end);
+
+            // gaps
+            if matches!(fields[4], "N" | "U") {
+                continue;
+            }
+            if fields.len() < 9 {
+                bail!("The AGP line {:?} has no orientation", line)
+            }
+            let component = Component {
+                object: object.clone(),
+                object_start,
+                object_end,
+                component: fields[5].to_string(),
+                component_start: position(fields[6])? - 1,
+                component_end: position(fields[7])?,
+                // unknown orientations are taken as forward
+                reversed: fields[8] == "-",
+            };
+            if component.object_end - component.object_start
+                != component.component_end - component.component_start
+            {
+                bail!(
+                    "The AGP line {:?} places a component in a different length of scaffold",
+                    line
+                )
+            }
+            let length = agp
+                .component_lengths
+                .entry(component.component.clone())
+                .or_insert(0);
+            *length = (*length).max(component.component_end);
+            let index = agp.components.len();
+            agp.by_object.entry(object).or_default().push(index);
+            agp.by_component
+                .entry(component.component.clone())
+                .or_default()
+                .push(index);
+            agp.components.push(component);
+        }
+        Ok(agp)
+    }
+
+    /// Lift the tract to the other coordinate system. A tract on a contig
+    /// goes to its scaffold, and a tract on a scaffold goes to its contig.
+    /// `None` if the record isn't in the AGP, or the tract isn't inside
+    /// a single component.
+    ///
+    /// A tract within `max_end_distance` of an end of the scaffold is at
+    /// its end, and one within that distance of an end of its contig,
+    /// but not of the scaffold, is internal. The breaks between pieces
+    /// of a contig split across scaffolds are not contig ends.
+    pub fn lift_tract(&self, tract: &Tract, max_end_distance: usize) -> Option<LiftedTract> {
+        let inside = |start: usize, end: usize| tract.start >= start && tract.end <= end;
+        let (component, on_component) = match self.by_component.get(&tract.id) {
+            Some(pieces) => (
+                pieces
+                    .iter()
+                    .map(|i| &self.components[*i])
+                    .find(|c| inside(c.component_start, c.component_end))?,
+                true,
+            ),
+            None => (
+                self.by_object
+                    .get(&tract.id)?
+                    .iter()
+                    .map(|i| &self.components[*i])
+                    .find(|c| inside(c.object_start, c.object_end))?,
+                false,
+            ),
+        };
+
+        // flip the interval in a reversed component
+        let map = |start: usize, end: usize, from_start: usize, to_start: usize, to_end: usize| {
+            let (s, e) = (start - from_start, end - from_start);
+            if component.reversed {
+                (to_end - e, to_end - s)
+            } else {
+                (to_start + s, to_start + e)
+            }
+        };
+        let ((object_start, object_end), (component_start, component_end)) = if on_component {
+            (
+                map(
+                    tract.start,
+                    tract.end,
+                    component.component_start,
+                    component.object_start,
+                    component.object_end,
+                ),
+                (tract.start, tract.end),
+            )
+        } else {
+            (
+                (tract.start, tract.end),
+                map(
+                    tract.start,
+                    tract.end,
+                    component.object_start,
+                    component.component_start,
+                    component.component_end,
+                ),
+            )
+        };
+
+        // the ends of the whole scaffold and contig, not of this piece
+        let near_end = |start: usize, end: usize, length: usize| {
+            start.min(length.saturating_sub(end)) <= max_end_distance
+        };
+        let object_length = self.object_lengths[&component.object];
+        let component_length = self.component_lengths[&component.component];
+        let position = if near_end(object_start, object_end, object_length) {
+            Position::End
+        } else if near_end(component_start, component_end, component_length) {
+            Position::Internal
+        } else {
+            Position::Interstitial
+        };
+
+        let strand = match (tract.strand, component.reversed) {
+            (strand, false) => strand,
+            (Strand::Forward, true) => Strand::Reverse,
+            (Strand::Reverse, true) => Strand::Forward,
+        };
+        Some(if on_component {
+            LiftedTract {
+                id: component.object.clone(),
+                start: object_start,
+                end: object_end,
+                strand,
+                position,
+            }
+        } else {
+            LiftedTract {
+                id: component.component.clone(),
+                start: component_start,
+                end: component_end,
+                strand,
+                position,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // scaffold_1 is ctg1, a 100bp gap, then ctg2 reversed
+    const AGP: &str = "##agp-version\t2.1\n\
+        scaffold_1\t1\t1000\t1\tW\tctg1\t1\t1000\t+\n\
+        scaffold_1\t1001\t1100\t2\tN\t100\tscaffold\tyes\tproximity_ligation\n\
+        scaffold_1\t1101\t3100\t3\tW\tctg2\t1\t2000\t-\n";
+
+    fn tract(id: &str, start: usize, end: usize) -> Tract {
+        Tract {
+            id: id.into(),
+            start,
+            end,
+            strand: Strand::Forward,
+            telomeric_repeat: "TTAGG".into(),
+            units: 10,
+            purity: 1.0,
+        }
+    }
+
+    #[test]
+    fn parse() {
+        let agp = Agp::parse(AGP.as_bytes()).unwrap();
+        assert_eq!(agp.components.len(), 2);
+        assert_eq!(agp.object_lengths["scaffold_1"], 3100);
+        assert_eq!(
+            agp.components[1],
+            Component {
+                object: "scaffold_1".into(),
+                object_start: 1100,
+                object_end: 3100,
+                component: "ctg2".into(),
+                component_start: 0,
+                component_end: 2000,
+                reversed: true,
+            }
+        );
+        assert!(Agp::parse("scaffold_1\t1\t10\t1\tW\tctg1\t1\t20\t+\n".as_bytes()).is_err());
+        assert!(Agp::parse("scaffold_1\t0\t10\t1\tW\tctg1\t1\t10\t+\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn lift() {
+        let agp = Agp::parse(AGP.as_bytes()).unwrap();
+
+        // the start of ctg1 is the start of the scaffold
+        let lifted = agp.lift_tract(&tract("ctg1", 0, 100), 50).unwrap();
+        assert_eq!(
+            (lifted.id.as_str(), lifted.start, lifted.end),
+            ("scaffold_1", 0, 100)
+        );
+        assert_eq!(lifted.position, Position::End);
+
+        // the start of ctg2 is reversed onto the end of the scaffold
+        let lifted = agp.lift_tract(&tract("ctg2", 0, 100), 50).unwrap();
+        assert_eq!((lifted.start, lifted.end), (3000, 3100));
+        assert_eq!(lifted.strand, Strand::Reverse);
+        assert_eq!(lifted.position, Position::End);
+
+        // the end of ctg2 ends up in the middle of the scaffold
+        let lifted = agp.lift_tract(&tract("ctg2", 1900, 2000), 50).unwrap();
+        assert_eq!((lifted.start, lifted.end), (1100, 1200));
+        assert_eq!(lifted.position, Position::Internal);
+        assert_eq!(
+            lifted_columns(Some(&lifted)),
+            "scaffold_1\t1100\t1200\t-\tinternal"
+        );
+
+        // and back down from the scaffold
+        let lifted = agp
+            .lift_tract(&tract("scaffold_1", 1100, 1200), 50)
+            .unwrap();
+        assert_eq!(
+            (lifted.id.as_str(), lifted.start, lifted.end),
+            ("ctg2", 1900, 2000)
+        );
+        assert_eq!(lifted.position, Position::Internal);
+        let lifted = agp.lift_tract(&tract("scaffold_1", 400, 500), 50).unwrap();
+        assert_eq!(lifted.position, Position::Interstitial);
+
+        // across the gap, or not in the AGP
+        assert!(agp
+            .lift_tract(&tract("scaffold_1", 950, 1150), 50)
+            .is_none());
+        assert!(agp.lift_tract(&tract("ctg3", 0, 100), 50).is_none());
+        assert_eq!(lifted_columns(None), ".\tNA\tNA\t.\tNA");
+    }
+
+    #[test]
+    fn lift_split_contig() {
+        // ctg3 is split across two scaffolds, and the break is not a
+        // contig end
+        let agp = Agp::parse(
+            "scaffold_1\t1\t1000\t1\tW\tctg1\t1\t1000\t+\n\
+            scaffold_1\t1001\t3000\t2\tW\tctg3\t1\t2000\t+\n\
+            scaffold_1\t3001\t5000\t3\tW\tctg4\t1\t2000\t+\n\
+            scaffold_2\t1\t1000\t1\tW\tctg3\t2001\t3000\t+\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        let lifted = agp.lift_tract(&tract("ctg3", 1900, 2000), 50).unwrap();
+        assert_eq!((lifted.start, lifted.end), (2900, 3000));
+        assert_eq!(lifted.position, Position::Interstitial);
+        let lifted = agp.lift_tract(&tract("ctg3", 2900, 3000), 50).unwrap();
+        assert_eq!(lifted.position, Position::End);
+        // the end of ctg4 is the end of scaffold_1, and the end of ctg1 is
+        // inside it
+        let lifted = agp.lift_tract(&tract("ctg4", 1900, 2000), 50).unwrap();
+        assert_eq!(lifted.position, Position::End);
+        let lifted = agp.lift_tract(&tract("scaffold_1", 950, 1000), 50).unwrap();
+        assert_eq!(lifted.position, Position::Internal);
+    }
+}
@@ -0,0 +1,395 @@
+use crate::{clades, explore, reader, utils, windows, SubCommand};
+use anyhow::{bail, Context, Result};
+use rayon::prelude::*;
+use std::collections::HashSet;
+use std::fmt::{self, Display};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, LineWriter, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Which subcommand is run on each genome of a batch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// `tidk find`, with the clade given in the manifest.
+    Find,
+    /// `tidk search`, with the telomeric repeat given in the manifest.
+    Search,
+    /// `tidk explore`, which needs nothing more in the manifest.
+    Explore,
+}
+
+impl Mode {
+    /// Parse the `--mode` value from the command line.
+    pub fn new(mode: &str) -> Self {
+        match mode {
+            "search" => Mode::Search,
+            "explore" => Mode::Explore,
+            _ => Mode::Find,
+        }
+    }
+
+    /// The header of the combined table. It is the header of the
+    /// subcommand's own table, with a sample column in front.
+    pub fn header(&self) -> &'static str {
+        match self {
+            Mode::Find | Mode::Search => {
+                "sample\tid\twindow\tforward_repeat_number\treverse_repeat_number\ttelomeric_repeat"
+            }
+            Mode::Explore => "sample\tcanonical_repeat_unit\tcount",
+        }
+    }
+}
+
+impl Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mode::Find => write!(f, "find"),
+            Mode::Search => write!(f, "search"),
+            Mode::Explore => write!(f, "explore"),
+        }
+    }
+}
+
+/// A genome listed in the batch manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sample {
+    /// The name of the sample, which goes in the first column
+    /// of the combined table.
+    pub name: String,
+    /// The input fasta (or anything else `tidk` can read).
+    pub fasta: PathBuf,
+    /// The clade (for find) or telomeric repeat (for search). Explore
+    /// doesn't need one.
+    pub telomeric_repeat: Option<String>,
+}
+
+/// Read a batch manifest, a tab separated file with one genome per line:
+/// the sample name, the path to the fasta, and the clade or telomeric
+/// repeat. Blank lines and lines starting with `#` are skipped.
+pub fn read_manifest<R: BufRead>(reader: R) -> Result<Vec<Sample>> {
+    let mut samples = Vec::new();
+    let mut names = HashSet::new();
+
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut columns = line.split('\t').map(str::trim);
+        let (Some(name), Some(fasta)) = (columns.next(), columns.next()) else {
+            bail!(
+                "Line {} of the manifest needs at least a sample name and a fasta path, separated by a tab.",
+                i + 1
+            )
+        };
+        if !names.insert(name.to_string()) {
+            bail!("Sample {} is in the manifest more than once.", name)
+        }
+        samples.push(Sample {
+            name: name.to_string(),
+            fasta: PathBuf::from(fasta),
+            telomeric_repeat: columns.next().filter(|c| !c.is_empty()).map(String::from),
+        });
+    }
+    Ok(samples)
+}
+
+/// How each genome of a batch is run. Only the options of the mode
+/// are used.
+#[derive(Debug, Clone)]
+pub struct BatchParams {
+    /// Which subcommand to run.
+    pub mode: Mode,
+    /// The window size, for find and search.
+    pub window_size: usize,
+    /// The step between windows, for find and search.
+    pub step: usize,
+    /// How approximate matches are counted, for find and search.
+    pub tolerance: utils::Tolerance,
+    /// Which matches are counted, for find and search.
+    pub count_mode: utils::CountMode,
+    /// The shortest and longest repeat lengths, for explore.
+    pub lengths: (usize, usize),
+    /// The fewest consecutive repeats reported, for explore.
+    pub threshold: i32,
+    /// The proportion of each record at either end, for explore.
+    pub distance: f64,
+    /// Which alignments are read from BAM or CRAM input.
+    pub alignment: reader::AlignmentOptions,
+}
+
+/// Run one genome of a batch, and write its rows of the combined table
+/// to `output` a record at a time, so a whole genome's rows are never
+/// held at once.
+pub fn run_sample<W: Write>(sample: &Sample, params: &BatchParams, output: &mut W) -> Result<()> {
+    if params.mode == Mode::Explore {
+        let estimates = explore::explore_fasta(
+            &sample.fasta,
+            &params.alignment,
+            params.lengths.0..=params.lengths.1,
+            params.threshold,
+            params.distance,
+        )?;
+        for (canonical_repeat_unit, count) in estimates {
+            writeln!(
+                output,
+                "{}\t{}\t{}",
+                sample.name, canonical_repeat_unit, count
+            )?;
+        }
+        return Ok(());
+    }
+
+    let telomeric_repeats: Vec<String> = match (params.mode, sample.telomeric_repeat.as_deref()) {
+        (_, None) => bail!("No clade or telomeric repeat in the manifest."),
+        (Mode::Find, Some(clade)) if !clades::CLADES.contains(&clade) => bail!(
+            "{} is not a clade tidk knows, see `tidk find --print`.",
+            clade
+        ),
+        (Mode::Find, Some(clade)) => clades::return_telomere_sequence(clade)
+            .seq
+            .0
+            .iter()
+            .map(|r| r.to_string())
+            .collect(),
+        (_, Some(repeat)) => vec![utils::parse_telomeric_repeat(repeat)?],
+    };
+    let telomeric_repeats: Vec<&str> = telomeric_repeats.iter().map(|r| r.as_str()).collect();
+    let window_params = windows::WindowParams {
+        window_size: params.window_size,
+        step: params.step,
+        format: windows::OutputFormat::Tsv,
+        tolerance: params.tolerance,
+        count_mode: params.count_mode,
+        tract_params: None,
+    };
+    let records = reader::records(&sample.fasta, &params.alignment)?;
+
+    for result in records {
+        let record = result?;
+        // only the counts are kept, the usual windows file isn't written
+        let (_, counts) = windows::count_record(&record, &telomeric_repeats, 0, &window_params)?;
+
+        windows::write_tsv_rows(output, Some(&sample.name), record.id(), &counts, None)?;
+    }
+    Ok(())
+}
+
+/// Run one genome of a batch into a spool file in the temporary
+/// directory, and copy its rows to the combined table only once the
+/// whole genome has succeeded, so a genome that fails partway leaves no
+/// rows behind. The spool file is removed either way.
+fn spool_sample<W: Write>(
+    sample: &Sample,
+    index: usize,
+    params: &BatchParams,
+    output: &Mutex<W>,
+) -> Result<()> {
+    let spool_path =
+        std::env::temp_dir().join(format!("tidk-batch-{}-{}.tsv", std::process::id(), index));
+    let result = (|| {
+        let mut spool = BufWriter::new(File::create(&spool_path)?);
+        run_sample(sample, params, &mut spool)?;
+        spool.flush()?;
+        drop(spool);
+
+        // the rows of a genome are written together
+        let mut spool = File::open(&spool_path)?;
+        let mut output = output.lock().expect("output lock poisoned");
+        std::io::copy(&mut spool, &mut *output)?;
+        Ok(())
+    })();
+    let _ = std::fs::remove_file(&spool_path);
+    result
+}
+
+/// The entry point for `tidk batch`.
+///
+/// Run find, search or explore on every genome in a manifest, in
+/// parallel, and write all the results to one table with a sample
+/// column. A genome that fails is reported, and the rest carry on.
+pub fn batch(matches: &clap::ArgMatches, sc: SubCommand) -> Result<()> {
+    let manifest = matches
+        .get_one::<PathBuf>("manifest")
+        .expect("errored by clap");
+    let manifest_file = File::open(manifest)
+        .with_context(|| format!("Failed to open manifest {}", manifest.display()))?;
+    let samples = read_manifest(BufReader::new(manifest_file))?;
+    if samples.is_empty() {
+        bail!("There are no samples in {}.", manifest.display())
+    }
+
+    // the genomes run in parallel on these threads, and so do the record
+    // ends of a genome in explore; find and search count the records of a
+    // genome one after another
+    if let Some(threads) = matches.get_one::<usize>("threads") {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(*threads)
+            .build_global()?;
+    }
+
+    let window_size = *matches
+        .get_one::<usize>("window")
+        .expect("defaulted by clap");
+    // by default, windows do not overlap
+    let step = *matches.get_one::<usize>("step").unwrap_or(&window_size);
+    if step == 0 || window_size == 0 {
+        bail!("The window size and step must both be greater than zero.")
+    }
+    // a single length, or a range of them
+    let lengths = match matches.get_one::<usize>("length") {
+        Some(length) => (*length, *length),
+        None => (
+            *matches
+                .get_one::<usize>("minimum")
+                .expect("defaulted by clap"),
+            *matches
+                .get_one::<usize>("maximum")
+                .expect("defaulted by clap"),
+        ),
+    };
+    let params = BatchParams {
+        mode: Mode::new(matches.get_one::<String>("mode").expect("errored by clap")),
+        window_size,
+        step,
+        tolerance: utils::Tolerance::new(
+            matches.get_one::<u8>("mismatches").copied(),
+            matches.get_one::<u8>("edits").copied(),
+        ),
+        count_mode: utils::CountMode::new(
+            matches
+                .get_one::<String>("count-mode")
+                .expect("defaulted by clap"),
+        ),
+        lengths,
+        threshold: *matches
+            .get_one::<i32>("threshold")
+            .expect("defaulted by clap"),
+        distance: *matches
+            .get_one::<f64>("distance")
+            .expect("defaulted by clap"),
+        alignment: reader::AlignmentOptions::from_matches(matches),
+    };
+    eprintln!(
+        "[+]\tRunning tidk {} on {} samples",
+        params.mode,
+        samples.len()
+    );
+
+    let output: Box<dyn Write + Send> = match matches.get_one::<PathBuf>("output") {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(std::io::stdout()),
+    };
+    let mut output = LineWriter::new(output);
+    writeln!(output, "{}", params.mode.header())?;
+    let output = Mutex::new(output);
+
+    let failed: Vec<&str> = samples
+        .par_iter()
+        .enumerate()
+        .filter_map(
+            |(index, sample)| match spool_sample(sample, index, &params, &output) {
+                Ok(()) => {
+                    eprintln!("[+]\tSample {} done", sample.name);
+                    None
+                }
+                Err(e) => {
+                    eprintln!("[-]\tSample {} failed: {:#}", sample.name, e);
+                    Some(sample.name.as_str())
+                }
+            },
+        )
+        .collect();
+
+    eprintln!(
+        "[+]\tFinished {} of {} samples",
+        samples.len() - failed.len(),
+        samples.len()
+    );
+
+    // optional log file
+    sc.log(matches)?;
+
+    if !failed.is_empty() {
+        bail!(
+            "{} of {} samples failed: {}",
+            failed.len(),
+            samples.len(),
+            failed.join(", ")
+        )
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::TempFile;
+
+    fn params(mode: Mode) -> BatchParams {
+        BatchParams {
+            mode,
+            window_size: 20,
+            step: 20,
+            tolerance: utils::Tolerance::Exact,
+            count_mode: utils::CountMode::Overlapping,
+            lengths: (5, 5),
+            threshold: 2,
+            distance: 0.5,
+            alignment: reader::AlignmentOptions::default(),
+        }
+    }
+
+    // run a sample, keeping all of its rows
+    fn rows(sample: &Sample, mode: Mode) -> Result<Vec<String>> {
+        let mut rows = Vec::new();
+        run_sample(sample, &params(mode), &mut rows)?;
+        Ok(String::from_utf8(rows)?
+            .lines()
+            .map(|row| row.to_owned())
+            .collect())
+    }
+
+    #[test]
+    fn manifest() {
+        let manifest = "# sample\tfasta\trepeat\nilAglIoxx1\tilAglIoxx1.fa.gz\tLepidoptera\n\ndrDroMela1\tdm.fa\t\n";
+        let samples = read_manifest(manifest.as_bytes()).unwrap();
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[0].fasta, PathBuf::from("ilAglIoxx1.fa.gz"));
+        assert_eq!(samples[0].telomeric_repeat.as_deref(), Some("Lepidoptera"));
+        assert_eq!(samples[1].telomeric_repeat, None);
+
+        assert!(read_manifest("only_a_name\n".as_bytes()).is_err());
+        assert!(read_manifest("a\ta.fa\na\tb.fa\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn samples() {
+        let file = TempFile::new("batch.fa");
+        let fasta = &file.path;
+        std::fs::write(fasta, format!(">chr1\n{}ACGTACGTAC\n", "TTAGG".repeat(4))).unwrap();
+        let sample = |telomeric_repeat: &str| Sample {
+            name: "s1".into(),
+            fasta: fasta.clone(),
+            telomeric_repeat: Some(telomeric_repeat.into()),
+        };
+
+        assert_eq!(
+            rows(&sample("ttagg"), Mode::Search).unwrap(),
+            vec!["s1\tchr1\t20\t4\t0\tTTAGG", "s1\tchr1\t30\t0\t0\tTTAGG"]
+        );
+
+        let found = rows(&sample("Lepidoptera"), Mode::Find).unwrap();
+        assert!(found.contains(&"s1\tchr1\t20\t0\t3\tAACCT".to_string()));
+
+        // a clade that isn't known, and a fasta that doesn't exist
+        assert!(rows(&sample("Lepidopterra"), Mode::Find).is_err());
+        let missing = Sample {
+            fasta: PathBuf::from("does-not-exist.fa"),
+            ..sample("TTAGG")
+        };
+        assert!(rows(&missing, Mode::Search).is_err());
+    }
+}
@@ -1,3 +1,5 @@
+use crate::utils::parse_telomeric_repeat;
+use anyhow::Result;
 use std::{
     boxed::Box,
     fmt::{self, Display},
@@ -152,7 +154,7 @@ pub static CLADES: &[&str] = &[
 
 /// A function to get a telomeric repeat sequence
 /// given a clade name.
-pub fn return_telomere_sequence(clade: &str) -> TelomereSeq {
+pub fn return_telomere_sequence(clade: &str) -> TelomereSeq<'_> {
     let result = match clade {
         "Accipitriformes" => TelomereSeq {
             clade: "Accipitriformes",
@@ -875,6 +877,24 @@ pub fn return_telomere_sequence(clade: &str) -> TelomereSeq {
 }
 // automated input end
 
+/// Get the telomeric repeats from the command line, either all the
+/// repeats of a clade from the clade table (`--clade`), or a single
+/// repeat given by the user (`--string`). The subcommands that take
+/// either of them all get their telomeric repeats from here.
+pub fn telomeric_repeats_from_matches(matches: &clap::ArgMatches) -> Result<Vec<String>> {
+    match matches.get_one::<String>("clade") {
+        Some(clade) => {
+            let clade_info = return_telomere_sequence(clade);
+            Ok(clade_info.seq.0.iter().map(|s| s.to_string()).collect())
+        }
+        None => Ok(vec![parse_telomeric_repeat(
+            matches
+                .get_one::<String>("string")
+                .expect("errored by clap"),
+        )?]),
+    }
+}
+
 /// Pretty print a table containing all the information about
 /// telomeric repeats that we currently have.
 pub fn print_table() {
@@ -1,14 +1,13 @@
-use crate::{utils, SubCommand};
+use crate::{reader, utils, SubCommand};
 use anyhow::bail;
 use anyhow::Result;
-use bio::io::fasta;
 use itertools::Itertools;
 use rayon::prelude::*;
 use std::collections::BTreeMap;
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
 use std::str;
-use std::sync::mpsc::channel;
 
 // when distance == 1, we get lower estimate of telomeric repeat number
 // than if we use distance == 0.1
@@ -45,6 +44,13 @@ pub fn explore(matches: &clap::ArgMatches, sc: SubCommand) -> Result<()> {
 
     let verbose = matches.get_flag("verbose");
 
+    let options = reader::AlignmentOptions::from_matches(matches);
+    let regions = reader::regions_from_matches(matches)?;
+
+    // the ends are read once, and explored for every length, so
+    // stdin isn't read again after it has been used up
+    let ends = read_ends(input_fasta, &regions, &options, dist_from_chromosome_end)?;
+
     // to report the telomeres...
     let mut output_vec: Vec<RepeatPositions> = Vec::new();
     // i.e. if you chose a length, as opposed to a minmum/maximum
@@ -53,36 +59,7 @@ pub fn explore(matches: &clap::ArgMatches, sc: SubCommand) -> Result<()> {
             "[+]\tExploring genome for potential telomeric repeats of length: {}",
             length
         );
-        let reader = fasta::Reader::from_file(input_fasta)?;
-
-        // try parallelising
-        let (sender, receiver) = channel();
-
-        reader
-            .records()
-            .par_bridge()
-            .for_each_with(sender, |s, record| {
-                let record = record.expect("[-]\tError during fasta record parsing.");
-                let id = record.id().to_owned();
-                let seq_len = record.seq().len();
-
-                let sequences = split_seq_by_distance(record, dist_from_chromosome_end, seq_len);
-
-                for sequence in sequences {
-                    let indexes = chunk_fasta(sequence, length, verbose, id.clone());
-
-                    if let Some(r) =
-                        calculate_indexes(indexes, length, verbose, id.clone(), threshold as usize)
-                    {
-                        s.send(r).expect("Did not send!");
-                    }
-                }
-            });
-
-        // this bit is a little chaotic
-        // collect output into a vector
-        let mut output = receiver.into_iter().collect();
-
+        let mut output = explore_length(&ends, length, threshold, verbose);
         output_vec.append(&mut output);
     } else {
         // if a range was chosen.
@@ -92,40 +69,7 @@ pub fn explore(matches: &clap::ArgMatches, sc: SubCommand) -> Result<()> {
         );
         for length in minimum..maximum + 1 {
             eprintln!("[+]\t\tFinding telomeric repeat length: {}", length);
-
-            // have to call reader in the loop, as otherwise `reader` doesn't live long enough.
-            // I expect it's not an expensive call anyway.
-            let reader = fasta::Reader::from_file(input_fasta)?;
-
-            // try parallelising
-            let (sender, receiver) = channel();
-            reader
-                .records()
-                .par_bridge()
-                .for_each_with(sender, |s, record| {
-                    let record = record.expect("[-]\tError during fasta record parsing.");
-                    let id = record.id().to_owned();
-                    let seq_len = record.seq().len();
-
-                    let sequences =
-                        split_seq_by_distance(record, dist_from_chromosome_end, seq_len);
-
-                    for sequence in sequences {
-                        let indexes = chunk_fasta(sequence, length, verbose, id.clone());
-
-                        if let Some(r) = calculate_indexes(
-                            indexes,
-                            length,
-                            verbose,
-                            id.clone(),
-                            threshold as usize,
-                        ) {
-                            s.send(r).expect("Did not send!");
-                        }
-                    }
-                });
-            let mut output = receiver.iter().collect();
-
+            let mut output = explore_length(&ends, length, threshold, verbose);
             output_vec.append(&mut output);
         }
     }
@@ -152,6 +96,77 @@ pub fn explore(matches: &clap::ArgMatches, sc: SubCommand) -> Result<()> {
     Ok(())
 }
 
+/// Read the ends of each record in `input_fasta`, as a proportion of
+/// its length, or the whole of each of the `regions` if there are any.
+/// If the fasta is indexed, only the ends of the records are read.
+fn read_ends(
+    input_fasta: &Path,
+    regions: &[reader::Region],
+    options: &reader::AlignmentOptions,
+    dist_from_chromosome_end: f64,
+) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut sequences = Vec::new();
+    if regions.is_empty() {
+        // only the ends are read, straight from the index if there is one
+        let ends = reader::end_records(
+            input_fasta,
+            reader::EndLength::Proportion(dist_from_chromosome_end),
+            options,
+        )?;
+        for ends in ends {
+            for end in ends? {
+                sequences.push((end.record.id().to_owned(), end.record.seq().to_vec()));
+            }
+        }
+    } else {
+        // the regions were chosen, so they are explored in full
+        for region in reader::region_records(input_fasta, regions, options)? {
+            let record = region?.record;
+            sequences.push((record.id().to_owned(), record.seq().to_vec()));
+        }
+    }
+    Ok(sequences)
+}
+
+/// Find the runs of potential telomeric repeats of one `length` in
+/// each of the record `ends`, searching the ends in parallel.
+fn explore_length(
+    ends: &[(String, Vec<u8>)],
+    length: usize,
+    threshold: i32,
+    verbose: bool,
+) -> Vec<RepeatPositions> {
+    ends.par_iter()
+        .filter_map(|(id, sequence)| {
+            let indexes = chunk_fasta(sequence, length, verbose, id.to_string());
+            calculate_indexes(indexes, length, verbose, id.to_string(), threshold as usize)
+        })
+        .collect()
+}
+
+/// Explore `input_fasta` for potential telomeric repeats of each of the
+/// `lengths`, and return the most likely ones, with their counts. This is
+/// `tidk explore` without the reporting, for running on many genomes.
+pub fn explore_fasta(
+    input_fasta: &Path,
+    options: &reader::AlignmentOptions,
+    lengths: RangeInclusive<usize>,
+    threshold: i32,
+    dist_from_chromosome_end: f64,
+) -> Result<Vec<(String, i32)>> {
+    if dist_from_chromosome_end > 0.5 {
+        bail!("Distance from chromosome end as a proportion can't be more than 0.5.")
+    }
+    let ends = read_ends(input_fasta, &[], options, dist_from_chromosome_end)?;
+    let mut repeat_positions = RepeatPositions::new();
+    for length in lengths {
+        for mut el in explore_length(&ends, length, threshold, false) {
+            repeat_positions.add(&mut el.0);
+        }
+    }
+    get_telomeric_repeat_estimates(&mut repeat_positions)
+}
+
 pub fn split_seq_by_distance(
     sequence: bio::io::fasta::Record,
     dist_from_chromosome_end: f64,
@@ -180,7 +195,7 @@ pub struct ChunkedFasta {
 /// and compare adjacent chunks for equality. Store the positions and sequences
 /// if they are equivalent.
 fn chunk_fasta(
-    sequence: Vec<u8>,
+    sequence: &[u8],
     chunk_length: usize,
     verbose: bool,
     id: String,
@@ -394,7 +409,6 @@ fn get_telomeric_repeat_estimates(
     telomeric_repeats: &mut RepeatPositions,
 ) -> Result<Vec<(String, i32)>> {
     let groups = telomeric_repeats.make_length_groups();
-    eprintln!("{:#?}", groups);
 
     // we need to compare all elements against all others
     let mut map: HashMap<String, i32> = HashMap::new();
@@ -442,7 +456,7 @@ fn get_telomeric_repeat_estimates(
     }
 
     let mut count_vec: Vec<_> = map.into_iter().collect();
-    count_vec.sort_by(|a, b| b.1.cmp(&a.1));
+    count_vec.sort_by_key(|a| std::cmp::Reverse(a.1));
     filter_count_vec(&mut count_vec)?;
 
     Ok(count_vec)
@@ -539,7 +553,7 @@ mod tests {
 
     fn generate_chunks_left(genome: &str) -> Vec<ChunkedFasta> {
         let left = &split_by_dist(genome)[0];
-        chunk_fasta(left.clone(), CHUNK_LENGTH, false, "".into())
+        chunk_fasta(left, CHUNK_LENGTH, false, "".into())
     }
 
     #[test]
@@ -571,7 +585,7 @@ mod tests {
 
     fn generate_chunks_right() -> Vec<ChunkedFasta> {
         let left = &split_by_dist(GENOME)[1];
-        chunk_fasta(left.clone(), CHUNK_LENGTH, false, "".into())
+        chunk_fasta(left, CHUNK_LENGTH, false, "".into())
     }
 
     #[test]
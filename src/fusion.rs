@@ -0,0 +1,244 @@
+use crate::tracts::Strand;
+use crate::windows::WindowCounts;
+
+/// How fusion junctions are called.
+#[derive(Debug, Clone, Copy)]
+pub struct FusionParams {
+    /// The fewest repeat units on each side of a junction.
+    pub min_repeats: usize,
+    /// Junctions closer than this (in bases) to either end
+    /// of a record are not reported.
+    pub min_distance: usize,
+}
+
+/// The header of the fusion TSV.
+pub const FUSIONS_HEADER: &str =
+    "id\tstart\tend\tjunction\torientation\tleft_repeat_number\tright_repeat_number\tsupport\ttelomeric_repeat";
+
+/// An inverted junction of telomeric arrays: an array on one strand
+/// directly followed by an array on the other strand.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fusion {
+    /// The record the junction is on.
+    pub id: String,
+    /// 0-based start of the window before the junction.
+    pub start: usize,
+    /// Exclusive end of the window after the junction.
+    pub end: usize,
+    /// Where the window before the junction ends.
+    pub junction: usize,
+    /// The strand of the array before the junction, the
+    /// array after is on the other strand.
+    pub left: Strand,
+    /// The telomeric repeat, as queried.
+    pub telomeric_repeat: String,
+    /// The number of repeat units on the left strand, before the junction.
+    pub left_repeat_number: usize,
+    /// The number of repeat units on the other strand, after the junction.
+    pub right_repeat_number: usize,
+    /// The number of repeat units on the expected strand, less those on
+    /// the other strand, on the weaker side of the junction.
+    pub support: usize,
+}
+
+impl Fusion {
+    /// The strands either side of the junction, e.g. `+/-`.
+    pub fn orientation(&self) -> String {
+        let right = match self.left {
+            Strand::Forward => Strand::Reverse,
+            Strand::Reverse => Strand::Forward,
+        };
+        format!("{}/{}", self.left, right)
+    }
+
+    /// Format as a row of the fusion TSV.
+    pub fn to_tsv(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            self.id,
+            self.start,
+            self.end,
+            self.junction,
+            self.orientation(),
+            self.left_repeat_number,
+            self.right_repeat_number,
+            self.support,
+            self.telomeric_repeat
+        )
+    }
+}
+
+/// Find the fusion signatures in the window counts of a record of
+/// `record_length` bases. Each window is compared with the next window
+/// that does not overlap it, and a junction is called where the first is
+/// dominated by one strand and the second by the other. Overlapping
+/// calls of the same orientation are merged, keeping the best supported.
+pub fn find_fusions(
+    id: &str,
+    counts: &WindowCounts,
+    record_length: usize,
+    params: FusionParams,
+) -> Vec<Fusion> {
+    let mut fusions: Vec<Fusion> = Vec::new();
+    let windows = &counts.windows;
+
+    for (i, &(start, junction)) in windows.iter().enumerate() {
+        // the next window starting after this one ends
+        let j = windows.partition_point(|w| w.0 < junction);
+        let Some(&(_, end)) = windows.get(j) else {
+            break;
+        };
+        if junction <= params.min_distance
+            || record_length.saturating_sub(junction) <= params.min_distance
+        {
+            continue;
+        }
+
+        let sides = [
+            (
+                Strand::Forward,
+                counts.forward[i],
+                counts.reverse[i],
+                counts.reverse[j],
+                counts.forward[j],
+            ),
+            (
+                Strand::Reverse,
+                counts.reverse[i],
+                counts.forward[i],
+                counts.forward[j],
+                counts.reverse[j],
+            ),
+        ];
+        for (left, left_repeat_number, left_other, right_repeat_number, right_other) in sides {
+            if left_repeat_number < params.min_repeats
+                || right_repeat_number < params.min_repeats
+                || left_repeat_number <= left_other
+                || right_repeat_number <= right_other
+            {
+                continue;
+            }
+            let fusion = Fusion {
+                id: id.to_string(),
+                start,
+                end,
+                junction,
+                left,
+                telomeric_repeat: counts.telomeric_repeat.clone(),
+                left_repeat_number,
+                right_repeat_number,
+                support: (left_repeat_number - left_other).min(right_repeat_number - right_other),
+            };
+
+            // calls of the two orientations can interleave, so merge with
+            // the last call of the same orientation
+            match fusions.iter_mut().rev().find(|f| f.left == left) {
+                Some(last) if fusion.start < last.end => {
+                    if fusion.support > last.support {
+                        *last = fusion;
+                    }
+                }
+                _ => fusions.push(fusion),
+            }
+        }
+    }
+    fusions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PARAMS: FusionParams = FusionParams {
+        min_repeats: 10,
+        min_distance: 100,
+    };
+
+    fn counts(
+        windows: Vec<(usize, usize)>,
+        forward: Vec<usize>,
+        reverse: Vec<usize>,
+    ) -> WindowCounts {
+        WindowCounts {
+            telomeric_repeat: "TTAGG".into(),
+            windows,
+            forward,
+            reverse,
+        }
+    }
+
+    #[test]
+    fn head_to_head() {
+        let c = counts(
+            vec![(0, 100), (100, 200), (200, 300), (300, 400), (400, 500)],
+            vec![0, 0, 18, 1, 0],
+            vec![0, 0, 2, 15, 0],
+        );
+        let fusions = find_fusions("test", &c, 500, PARAMS);
+        assert_eq!(fusions.len(), 1);
+        assert_eq!(
+            fusions[0].to_tsv(),
+            "test\t200\t400\t300\t+/-\t18\t15\t14\tTTAGG"
+        );
+    }
+
+    #[test]
+    fn near_ends() {
+        // a correctly oriented telomere at each end is not a fusion
+        let c = counts(
+            vec![(0, 100), (100, 200), (200, 300)],
+            vec![0, 0, 20],
+            vec![20, 0, 0],
+        );
+        assert!(find_fusions("test", &c, 300, PARAMS).is_empty());
+
+        // nor is a junction too close to an end
+        let c = counts(vec![(0, 100), (100, 200)], vec![20, 0], vec![0, 20]);
+        assert!(find_fusions("test", &c, 200, PARAMS).is_empty());
+    }
+
+    #[test]
+    fn sliding_windows() {
+        // overlapping windows see the same junction several times
+        let c = counts(
+            vec![
+                (0, 100),
+                (50, 150),
+                (100, 200),
+                (150, 250),
+                (200, 300),
+                (250, 350),
+                (300, 400),
+            ],
+            vec![0, 5, 12, 11, 0, 0, 0],
+            vec![0, 0, 0, 1, 12, 11, 0],
+        );
+        let fusions = find_fusions("test", &c, 400, PARAMS);
+        assert_eq!(fusions.len(), 1);
+        assert_eq!((fusions[0].junction, fusions[0].support), (200, 12));
+        assert_eq!(fusions[0].orientation(), "+/-");
+    }
+
+    #[test]
+    fn alternating_calls() {
+        // sliding windows call +/-, then -/+, then +/- again over the
+        // first call, which is the same junction
+        let c = counts(
+            vec![
+                (0, 100),
+                (50, 150),
+                (100, 200),
+                (150, 250),
+                (200, 300),
+                (250, 350),
+                (300, 400),
+            ],
+            vec![0, 12, 0, 0, 12, 0, 0],
+            vec![0, 0, 12, 12, 0, 0, 12],
+        );
+        let fusions = find_fusions("test", &c, 500, PARAMS);
+        let orientations: Vec<String> = fusions.iter().map(|f| f.orientation()).collect();
+        assert_eq!(orientations, vec!["+/-", "-/+"]);
+        assert_eq!((fusions[0].start, fusions[0].junction), (50, 150));
+    }
+}
@@ -0,0 +1,392 @@
+use crate::tracts::{self, End};
+use crate::{clades, reader, utils, windows, SubCommand};
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, LineWriter, Write};
+use std::path::PathBuf;
+
+/// The header of the `tidk gfa` output.
+pub const GFA_HEADER: &str =
+    "segment\tlength\tend\ttract_length\trepeat_number\tdistance_from_end\tstrand\tlinks\ttip";
+
+/// The Bandage colour of a segment with a telomeric tip.
+pub const TIP_COLOUR: &str = "red";
+
+/// The Bandage colour of a segment with a telomere at an end
+/// that is linked to other segments.
+pub const LINKED_COLOUR: &str = "orange";
+
+/// How the telomeres of the segments are found.
+#[derive(Debug, Clone)]
+pub struct GfaParams {
+    /// The telomeric repeats to match.
+    pub telomeric_repeats: Vec<String>,
+    /// How the matches are merged into tracts.
+    pub tract_params: tracts::TractParams,
+    /// The furthest a tract can be from the end of a segment.
+    pub max_end_distance: usize,
+}
+
+/// A segment of the graph with a telomere at one or both ends.
+#[derive(Debug, Clone)]
+pub struct Segment {
+    /// The name of the segment.
+    pub name: String,
+    /// The length of the segment sequence.
+    pub length: usize,
+    /// The telomeres at the start and end of the segment.
+    pub ends: [tracts::TelomereEnd; 2],
+}
+
+/// The telomeric segments of a graph, and how many links
+/// there are to each end of each segment.
+#[derive(Debug, Default)]
+pub struct Graph {
+    /// The segments with a telomere, in the order they are in the file.
+    pub segments: Vec<Segment>,
+    /// The number of links to each segment end.
+    pub links: HashMap<(String, End), usize>,
+    /// The number of segments scanned.
+    pub scanned: usize,
+    /// The number of segments with no sequence, which can't be scanned.
+    pub without_sequence: usize,
+}
+
+impl Graph {
+    /// The number of links to one end of a segment.
+    pub fn links_to(&self, name: &str, end: End) -> usize {
+        self.links
+            .get(&(name.to_string(), end))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Is this end of the segment a telomeric tip, a telomere
+    /// with no links to the rest of the graph?
+    pub fn is_tip(&self, segment: &Segment, end: End) -> bool {
+        let telomere = match end {
+            End::Five => &segment.ends[0],
+            End::Three => &segment.ends[1],
+        };
+        telomere.tract.is_some() && self.links_to(&segment.name, end) == 0
+    }
+
+    /// The Bandage colour of a segment: tips first, then
+    /// telomeres at linked ends.
+    pub fn colour(&self, segment: &Segment) -> &'static str {
+        if [End::Five, End::Three]
+            .iter()
+            .any(|end| self.is_tip(segment, *end))
+        {
+            TIP_COLOUR
+        } else {
+            LINKED_COLOUR
+        }
+    }
+
+    /// The rows of the `tidk gfa` output for one segment, one
+    /// for each end with a telomere.
+    pub fn to_tsv(&self, segment: &Segment) -> Vec<String> {
+        segment
+            .ends
+            .iter()
+            .filter_map(|telomere| {
+                let tract = telomere.tract.as_ref()?;
+                Some(format!(
+                    "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                    segment.name,
+                    segment.length,
+                    telomere.end,
+                    tract.length(),
+                    tract.units,
+                    telomere.distance.unwrap_or(0),
+                    tract.strand,
+                    self.links_to(&segment.name, telomere.end),
+                    if self.is_tip(segment, telomere.end) {
+                        "yes"
+                    } else {
+                        "no"
+                    }
+                ))
+            })
+            .collect()
+    }
+}
+
+/// The two segment ends joined by a GFA link line. Leaving a segment
+/// on the forward strand is from its 3' end, and entering a segment on
+/// the forward strand is at its 5' end.
+pub fn link_ends(line: &str) -> Result<[(String, End); 2]> {
+    let fields: Vec<&str> = line.split('\t').collect();
+    if fields.len() < 5 {
+        bail!("The link {:?} has fewer than five fields", line)
+    }
+    let orientation = |field: &str| match field {
+        "+" => Ok(true),
+        "-" => Ok(false),
+        _ => bail!("The link {:?} has an orientation of {:?}", line, field),
+    };
+    let from = if orientation(fields[2])? {
+        End::Three
+    } else {
+        End::Five
+    };
+    let to = if orientation(fields[4])? {
+        End::Five
+    } else {
+        End::Three
+    };
+    Ok([(fields[1].to_string(), from), (fields[3].to_string(), to)])
+}
+
+/// Read a GFA (version 1) graph, finding the telomeres at the ends of the
+/// segments, and the links to them. Segments without a sequence (`*`)
+/// are skipped, and only segment and link lines are read.
+pub fn read_graph<R: BufRead>(reader: R, params: &GfaParams) -> Result<Graph> {
+    let telomeric_repeats: Vec<&str> = params
+        .telomeric_repeats
+        .iter()
+        .map(|s| s.as_str())
+        .collect();
+    let mut graph = Graph::default();
+
+    for line in reader.lines() {
+        let line = line.context("Failed to read the GFA")?;
+        if line.starts_with("L\t") {
+            for end in link_ends(&line)? {
+                *graph.links.entry(end).or_insert(0) += 1;
+            }
+            continue;
+        }
+        if !line.starts_with("S\t") {
+            continue;
+        }
+
+        let mut fields = line.split('\t').skip(1);
+        let (Some(name), Some(sequence)) = (fields.next(), fields.next()) else {
+            bail!("The segment {:?} has no sequence field", line)
+        };
+        if sequence == "*" {
+            graph.without_sequence += 1;
+            continue;
+        }
+        graph.scanned += 1;
+
+        let motifs = windows::find_telomeric_repeats(
+            sequence.as_bytes(),
+            &telomeric_repeats,
+            utils::Tolerance::Exact,
+        )?;
+        let segment_tracts = tracts::call_record_tracts(
+            name,
+            &telomeric_repeats,
+            &motifs,
+            utils::CountMode::Overlapping,
+            params.tract_params,
+        );
+        let ends = tracts::telomere_ends(
+            name,
+            sequence.len(),
+            &segment_tracts,
+            params.max_end_distance,
+        );
+        if ends.iter().any(|e| e.tract.is_some()) {
+            graph.segments.push(Segment {
+                name: name.to_string(),
+                length: sequence.len(),
+                ends,
+            });
+        }
+    }
+    Ok(graph)
+}
+
+/// Add a Bandage colour tag to a segment line, replacing
+/// any colour it already has, whatever the type of its tag.
+pub fn colour_segment(line: &str, colour: &str) -> String {
+    let mut fields: Vec<&str> = line
+        .split('\t')
+        .filter(|field| !field.starts_with("CL:"))
+        .collect();
+    let tag = format!("CL:z:{}", colour);
+    fields.push(&tag);
+    fields.join("\t")
+}
+
+/// The entry point for `tidk gfa`.
+///
+/// Find the segments of an assembly graph with telomeres at their ends,
+/// and whether those ends are tips of the graph.
+pub fn gfa(matches: &clap::ArgMatches, sc: SubCommand) -> Result<()> {
+    let input_gfa = matches.get_one::<PathBuf>("gfa").expect("errored by clap");
+    let annotate = matches.get_one::<PathBuf>("annotate");
+    if annotate.is_some() && reader::is_stdin(input_gfa) {
+        bail!("The graph is read twice to annotate it, so it can't be read from stdin")
+    }
+
+    let params = GfaParams {
+        telomeric_repeats: clades::telomeric_repeats_from_matches(matches)?,
+        tract_params: tracts::TractParams {
+            max_gap: *matches
+                .get_one::<usize>("max-gap")
+                .expect("defaulted by clap"),
+            min_length: *matches
+                .get_one::<usize>("min-tract-length")
+                .expect("defaulted by clap"),
+        },
+        max_end_distance: *matches
+            .get_one::<usize>("max-end-distance")
+            .expect("defaulted by clap"),
+    };
+    eprintln!(
+        "[+]\tChecking segment ends using telomeric repeat(s): {}",
+        params.telomeric_repeats.join(", ")
+    );
+
+    let graph = read_graph(BufReader::new(reader::open(input_gfa)?), &params)
+        .with_context(|| format!("Failed to read {}", input_gfa.display()))?;
+    if graph.without_sequence > 0 {
+        eprintln!(
+            "[-]\t{} segments have no sequence, and were skipped",
+            graph.without_sequence
+        );
+    }
+
+    println!("{}", GFA_HEADER);
+    for segment in &graph.segments {
+        for row in graph.to_tsv(segment) {
+            println!("{}", row);
+        }
+    }
+
+    let telomeres: Vec<(&Segment, End)> = graph
+        .segments
+        .iter()
+        .flat_map(|s| {
+            s.ends
+                .iter()
+                .filter(|e| e.tract.is_some())
+                .map(move |e| (s, e.end))
+        })
+        .collect();
+    let tips = telomeres
+        .iter()
+        .filter(|(segment, end)| graph.is_tip(segment, *end))
+        .count();
+    eprintln!("[+]\tSegments checked: {}", graph.scanned);
+    eprintln!(
+        "[+]\tSegment ends with a telomere: {}, of which {} are tips",
+        telomeres.len(),
+        tips
+    );
+
+    // copy the graph, colouring the telomeric segments
+    if let Some(annotate) = annotate {
+        let colours: HashMap<&str, &str> = graph
+            .segments
+            .iter()
+            .map(|s| (s.name.as_str(), graph.colour(s)))
+            .collect();
+        let mut annotated = LineWriter::new(File::create(annotate)?);
+        for line in BufReader::new(reader::open(input_gfa)?).lines() {
+            let line = line?;
+            let colour = line
+                .strip_prefix("S\t")
+                .and_then(|rest| rest.split('\t').next())
+                .and_then(|name| colours.get(name));
+            match colour {
+                Some(colour) => writeln!(annotated, "{}", colour_segment(&line, colour))?,
+                None => writeln!(annotated, "{}", line)?,
+            }
+        }
+        eprintln!(
+            "[+]\tWrote the graph with telomeric tips in {} and other telomeres in {} to {}",
+            TIP_COLOUR,
+            LINKED_COLOUR,
+            annotate.display()
+        );
+    }
+
+    // optional log file
+    sc.log(matches)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params() -> GfaParams {
+        GfaParams {
+            telomeric_repeats: vec!["TTAGG".into()],
+            tract_params: tracts::TractParams {
+                max_gap: 5,
+                min_length: 20,
+            },
+            max_end_distance: 10,
+        }
+    }
+
+    #[test]
+    fn links() {
+        let [from, to] = link_ends("L\ts1\t+\ts2\t-\t0M").unwrap();
+        assert_eq!(from, ("s1".to_string(), End::Three));
+        assert_eq!(to, ("s2".to_string(), End::Three));
+        let [from, to] = link_ends("L\ts1\t-\ts2\t+\t0M").unwrap();
+        assert_eq!(from, ("s1".to_string(), End::Five));
+        assert_eq!(to, ("s2".to_string(), End::Five));
+        assert!(link_ends("L\ts1\t+\ts2").is_err());
+        assert!(link_ends("L\ts1\t+\ts2\t?\t0M").is_err());
+    }
+
+    #[test]
+    fn tips() {
+        let telomere = "CCTAA".repeat(6);
+        let gfa = format!(
+            "H\tVN:Z:1.0\n\
+             S\ts1\t{telomere}ACGTACGTACGTACGTACGT\n\
+             S\ts2\tACGTACGTACGTACGTACGT{telomere}\n\
+             S\ts3\t*\tLN:i:100\n\
+             S\ts4\tACGTACGTACGTACGTACGTACGT\n\
+             L\ts1\t+\ts4\t+\t0M\n\
+             L\ts4\t+\ts2\t-\t0M\n"
+        );
+        let graph = read_graph(gfa.as_bytes(), &params()).unwrap();
+
+        assert_eq!(graph.scanned, 3);
+        assert_eq!(graph.without_sequence, 1);
+        assert_eq!(graph.segments.len(), 2);
+        // s1 has a telomere at its unlinked start
+        let s1 = &graph.segments[0];
+        assert!(graph.is_tip(s1, End::Five));
+        assert!(!graph.is_tip(s1, End::Three));
+        assert_eq!(graph.colour(s1), TIP_COLOUR);
+        assert_eq!(
+            graph.to_tsv(s1),
+            vec!["s1\t50\t5'\t30\t6\t0\t-\t0\tyes".to_string()]
+        );
+        // s2 is entered on the reverse strand, so its 3' end is linked
+        let s2 = &graph.segments[1];
+        assert!(!graph.is_tip(s2, End::Three));
+        assert_eq!(graph.colour(s2), LINKED_COLOUR);
+        assert_eq!(graph.to_tsv(s2)[0], "s2\t50\t3'\t30\t6\t0\t-\t1\tno");
+    }
+
+    #[test]
+    fn colours() {
+        assert_eq!(
+            colour_segment("S\ts1\tACGT\tLN:i:4", TIP_COLOUR),
+            "S\ts1\tACGT\tLN:i:4\tCL:z:red"
+        );
+        assert_eq!(
+            colour_segment("S\ts1\tACGT\tCL:z:blue\tLN:i:4", LINKED_COLOUR),
+            "S\ts1\tACGT\tLN:i:4\tCL:z:orange"
+        );
+        assert_eq!(
+            colour_segment("S\ts1\tACGT\tCL:Z:blue", TIP_COLOUR),
+            "S\ts1\tACGT\tCL:z:red"
+        );
+    }
+}
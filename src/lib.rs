@@ -3,6 +3,11 @@ use chrono::Local;
 use clap::crate_version;
 use std::{io::Write, path::PathBuf};
 
+/// Lifting telomeric tracts between contigs and scaffolds
+/// with an AGP.
+pub mod agp;
+/// The entry point for the `tidk batch` subcommand.
+pub mod batch;
 /// A module where the clades are defined, and their
 /// respective telomeric repeats are enumerated.
 pub mod clades;
@@ -10,19 +15,167 @@ pub mod clades;
 pub mod explore;
 /// The entry point for the `tidk find` subcommand.
 pub mod finder;
+/// Finding the inverted junctions of telomeric arrays left
+/// by end to end chromosome fusions.
+pub mod fusion;
+/// The entry point for the `tidk gfa` subcommand.
+pub mod gfa;
 /// Functions to plot output from `tidk search` and
 /// `tidk find`.
 pub mod plot;
+/// The entry point for the `tidk qc` subcommand.
+pub mod qc;
+/// Reading fasta, fastq, BAM, CRAM or 2bit input, or just
+/// some regions of it.
+pub mod reader;
 /// The entry point for the `tidk search` subcommand.
 pub mod search;
+/// The entry point for the `tidk support` subcommand.
+pub mod support;
+/// Calling contiguous telomeric repeat tracts from
+/// the matches found by `tidk search` and `tidk find`.
+pub mod tracts;
+/// Reading UCSC 2bit genomes, any part of
+/// a record at a time.
+pub mod twobit;
 /// Module for utilities.
 pub mod utils;
+/// Counting telomeric repeats in windows along each record,
+/// for `tidk search` and `tidk find`.
+pub mod windows;
 
-/// Three possible subcommands.
+/// Seven possible subcommands.
 pub enum SubCommand {
+    Batch,
     Find,
     Explore,
+    Gfa,
     Search,
+    Qc,
+    Support,
+}
+
+/// Describe the telomeric tract options for the log.
+fn tracts_log(matches: &clap::ArgMatches) -> String {
+    if matches.get_flag("tracts") || matches.get_flag("ends-summary") || matches.get_flag("its") {
+        let mut log = format!(
+            "maximum gap {}bp, minimum length {}bp",
+            matches
+                .get_one::<usize>("max-gap")
+                .expect("defaulted by clap"),
+            matches
+                .get_one::<usize>("min-tract-length")
+                .expect("defaulted by clap")
+        );
+        if matches.get_flag("ends-summary") {
+            log += &format!(
+                ", end orientation checked with at least {} repeat units",
+                matches
+                    .get_one::<usize>("min-end-repeats")
+                    .expect("defaulted by clap")
+            );
+        }
+        if matches.get_flag("its") {
+            log += &format!(
+                ", interstitial at least {}bp from either end",
+                matches
+                    .get_one::<usize>("its-distance")
+                    .expect("defaulted by clap")
+            );
+        }
+        if let Some(agp) = matches.get_one::<PathBuf>("agp") {
+            log += &format!(", lifted with the AGP {}", agp.display());
+        }
+        log
+    } else {
+        "not called".into()
+    }
+}
+
+/// Describe the regions processed for the log.
+fn regions_log(matches: &clap::ArgMatches) -> String {
+    // explore has no ends-only mode
+    if let Ok(Some(length)) = matches.try_get_one::<String>("ends-only") {
+        return format!("only {} at each end of each record", length);
+    }
+    let mut regions = Vec::new();
+    if let Some(bed) = matches.get_one::<PathBuf>("regions") {
+        regions.push(format!("those in {}", bed.display()));
+    }
+    if let Some(strings) = matches.get_many::<String>("region") {
+        regions.extend(strings.cloned());
+    }
+    if regions.is_empty() {
+        "whole records".into()
+    } else {
+        regions.join(", ")
+    }
+}
+
+/// Describe the fusion options for the log.
+fn fusions_log(matches: &clap::ArgMatches) -> String {
+    if matches.get_flag("fusions") {
+        format!(
+            "at least {} repeat units either side, at least {}bp from either end",
+            matches
+                .get_one::<usize>("fusion-min-repeats")
+                .expect("defaulted by clap"),
+            matches
+                .get_one::<usize>("its-distance")
+                .expect("defaulted by clap")
+        )
+    } else {
+        "not called".into()
+    }
+}
+
+/// Describe the window counting options of `tidk find` and
+/// `tidk search` for the log.
+fn windows_log(matches: &clap::ArgMatches) -> String {
+    let window_size = *matches.get_one::<usize>("window").expect("errored by clap");
+    let step = *matches.get_one::<usize>("step").unwrap_or(&window_size);
+    let extension = matches
+        .get_one::<String>("extension")
+        .expect("defaulted by clap");
+    let tolerance = utils::Tolerance::new(
+        matches.get_one::<u8>("mismatches").copied(),
+        matches.get_one::<u8>("edits").copied(),
+    );
+    let count_mode = utils::CountMode::new(
+        matches
+            .get_one::<String>("count-mode")
+            .expect("defaulted by clap"),
+    );
+    format!(
+        "Window size: {}\n    Window step: {}\n    Window counts: {}\n    Matching: {}\n    Count mode: {}",
+        window_size, step, extension, tolerance, count_mode
+    )
+}
+
+/// The window counts file of `tidk find` or `tidk search`, and the
+/// log file, which goes next to the output files if there are any.
+fn windows_log_files(matches: &clap::ArgMatches, subcommand: &str) -> (String, String) {
+    let extension = matches
+        .get_one::<String>("extension")
+        .expect("defaulted by clap");
+    match (
+        matches.get_one::<PathBuf>("dir"),
+        matches.get_one::<PathBuf>("output"),
+    ) {
+        (Some(outdir), Some(output)) if !matches.get_flag("stdout") => (
+            format!(
+                "{}/{}_telomeric_repeat_windows.{}",
+                outdir.display(),
+                output.display(),
+                extension
+            ),
+            format!("{}/{}{}", outdir.display(), output.display(), ".log"),
+        ),
+        _ => (
+            "printed to STDOUT".into(),
+            format!("tidk-{}.log", subcommand),
+        ),
+    }
 }
 
 /// A date format.
@@ -37,23 +190,12 @@ impl SubCommand {
         if matches.get_flag("log") {
             match self {
                 SubCommand::Find => {
-                    let output = matches
-                        .get_one::<PathBuf>("output")
-                        .expect("errored by clap");
-                    let outdir = matches.get_one::<PathBuf>("dir").expect("errored by clap");
                     let input_fasta = matches
                         .get_one::<PathBuf>("fasta")
                         .expect("errored by clap");
                     let clade = matches.get_one::<String>("clade").expect("errored by clap");
-                    let clade_info = clades::return_telomere_sequence(clade);
-                    let window_size = *matches.get_one::<usize>("window").expect("errored by clap");
-
-                    let file_name = format!(
-                        "{}/{}{}",
-                        outdir.display(),
-                        output.display(),
-                        "_telomeric_repeat_windows.csv"
-                    );
+                    let telomeric_repeats = clades::telomeric_repeats_from_matches(matches)?;
+                    let (file_name, log_file_name) = windows_log_files(matches, "find");
 
                     let log_string = format!(
                         r#"tidk version: {}
@@ -61,27 +203,116 @@ Log information for output file: {}
 Date: {}
 `tidk find` was run with the following parameters:
     Input fasta: {}
-    Window size: {}
+    Regions: {}
     Clade chosen: {}
-    Telomeric repeats queried: {}"#,
+    Telomeric repeats queried: {}
+    {}
+    Telomeric tracts: {}
+    Fusions: {}"#,
                         crate_version!(),
                         file_name,
                         Local::now().format(DATE_FORMAT_STR),
                         input_fasta.display(),
-                        window_size,
+                        regions_log(matches),
                         clade,
-                        clade_info.seq.0.join(", ")
+                        telomeric_repeats.join(", "),
+                        windows_log(matches),
+                        tracts_log(matches),
+                        fusions_log(matches)
                     );
 
                     // create file
-                    let log_file_name =
-                        format!("{}/{}{}", outdir.display(), output.display(), ".log");
                     let log_file = std::fs::File::create(&log_file_name)?;
                     let mut log_file = std::io::LineWriter::new(log_file);
 
                     writeln!(log_file, "{}", log_string)?;
 
-                    Ok(eprintln!("[+]\tLog file written to: {}", log_file_name))
+                    eprintln!("[+]\tLog file written to: {}", log_file_name);
+                    Ok(())
+                }
+                SubCommand::Batch => {
+                    let manifest = matches
+                        .get_one::<PathBuf>("manifest")
+                        .expect("errored by clap");
+                    let mode = matches.get_one::<String>("mode").expect("errored by clap");
+                    let output = match matches.get_one::<PathBuf>("output") {
+                        Some(output) => output.display().to_string(),
+                        None => "printed to STDOUT".into(),
+                    };
+                    let threads = match matches.get_one::<usize>("threads") {
+                        Some(threads) => threads.to_string(),
+                        None => "all".into(),
+                    };
+                    let mode_info = if mode == "explore" {
+                        let lengths = match matches.get_one::<usize>("length") {
+                            Some(length) => length.to_string(),
+                            None => format!(
+                                "{} to {}",
+                                matches
+                                    .get_one::<usize>("minimum")
+                                    .expect("defaulted by clap"),
+                                matches
+                                    .get_one::<usize>("maximum")
+                                    .expect("defaulted by clap")
+                            ),
+                        };
+                        format!(
+                            "lengths {}, threshold {}, {}% distance from chromosome end",
+                            lengths,
+                            matches
+                                .get_one::<i32>("threshold")
+                                .expect("defaulted by clap"),
+                            matches
+                                .get_one::<f64>("distance")
+                                .expect("defaulted by clap")
+                                * 100.0
+                        )
+                    } else {
+                        let window_size =
+                            matches.get_one::<usize>("window").expect("errored by clap");
+                        format!(
+                            "window size {}, window step {}, matching {}, count mode {}",
+                            window_size,
+                            matches.get_one::<usize>("step").unwrap_or(window_size),
+                            utils::Tolerance::new(
+                                matches.get_one::<u8>("mismatches").copied(),
+                                matches.get_one::<u8>("edits").copied(),
+                            ),
+                            utils::CountMode::new(
+                                matches
+                                    .get_one::<String>("count-mode")
+                                    .expect("defaulted by clap"),
+                            )
+                        )
+                    };
+
+                    let log_string = format!(
+                        r#"tidk version: {}
+Log information for output file: {}
+Date: {}
+`tidk batch` was run with the following parameters:
+    Manifest: {}
+    Mode: {}
+    Parameters: {}
+    Threads: {}"#,
+                        crate_version!(),
+                        output,
+                        Local::now().format(DATE_FORMAT_STR),
+                        manifest.display(),
+                        mode,
+                        mode_info,
+                        threads
+                    );
+
+                    // create file
+                    let log_file_name = "tidk-batch.log".to_string();
+                    let log_file = std::fs::File::create(&log_file_name)?;
+                    let mut log_file = std::io::LineWriter::new(log_file);
+
+                    writeln!(log_file, "{}", log_string)?;
+
+                    eprintln!("[+]\tLog file written to: {}", log_file_name);
+                    Ok(())
                 }
                 SubCommand::Explore => {
                     let input_fasta = matches
@@ -102,6 +333,7 @@ Log information for output files: printed to STDOUT
 Date: {}
 `tidk explore` was run with the following parameters:
     Input fasta: {}
+    Regions: {}
     Explored telomeric repeat units of length: {}
     Or from length: {}
     To length: {}
@@ -110,6 +342,7 @@ Date: {}
                         crate_version!(),
                         Local::now().format(DATE_FORMAT_STR),
                         input_fasta.display(),
+                        regions_log(matches),
                         {
                             if let Some(l) = length {
                                 l.to_string()
@@ -144,60 +377,210 @@ Date: {}
 
                     writeln!(log_file, "{}", log_string)?;
 
-                    Ok(eprintln!("[+]\tLog file written to: {}", log_file_name))
+                    eprintln!("[+]\tLog file written to: {}", log_file_name);
+                    Ok(())
                 }
-                SubCommand::Search => {
+                SubCommand::Qc => {
                     let input_fasta = matches
                         .get_one::<PathBuf>("fasta")
                         .expect("errored by clap");
-                    let telomeric_repeat = matches
-                        .get_one::<String>("string")
-                        .expect("errored by clap");
-                    let extension = matches
-                        .get_one::<String>("extension")
-                        .expect("errored by clap");
+                    let telomeric_repeat = match matches.get_one::<String>("clade") {
+                        Some(clade) => format!("repeats of clade {}", clade),
+                        None => matches
+                            .get_one::<String>("string")
+                            .expect("errored by clap")
+                            .to_string(),
+                    };
+                    let max_gap = matches
+                        .get_one::<usize>("max-gap")
+                        .expect("defaulted by clap");
+                    let min_tract_length = matches
+                        .get_one::<usize>("min-tract-length")
+                        .expect("defaulted by clap");
+                    let max_end_distance = matches
+                        .get_one::<usize>("max-end-distance")
+                        .expect("defaulted by clap");
+                    let min_length = matches
+                        .get_one::<usize>("min-length")
+                        .expect("defaulted by clap");
 
-                    let window_size = matches.get_one::<usize>("window").expect("errored by clap");
-                    let outdir = matches.get_one::<PathBuf>("dir").expect("errored by clap");
-                    let output = matches
-                        .get_one::<PathBuf>("output")
-                        .expect("errored by clap");
+                    let log_string = format!(
+                        r#"tidk version: {}
+Log information for output files: printed to STDOUT
+Date: {}
+`tidk qc` was run with the following parameters:
+    Input fasta: {}
+    Telomeric repeat(s): {}
+    Telomeric tracts: maximum gap {}bp, minimum length {}bp, maximum distance from end {}bp
+    Minimum record length: {}"#,
+                        crate_version!(),
+                        Local::now().format(DATE_FORMAT_STR),
+                        input_fasta.display(),
+                        telomeric_repeat,
+                        max_gap,
+                        min_tract_length,
+                        max_end_distance,
+                        min_length
+                    );
 
                     // create file
-                    let file_name = format!(
-                        "{}/{}{}{}",
-                        outdir.display(),
-                        output.display(),
-                        "_telomeric_repeat_windows.",
-                        extension
+                    let log_file_name = "tidk-qc.log".to_string();
+                    let log_file = std::fs::File::create(&log_file_name)?;
+                    let mut log_file = std::io::LineWriter::new(log_file);
+
+                    writeln!(log_file, "{}", log_string)?;
+
+                    eprintln!("[+]\tLog file written to: {}", log_file_name);
+                    Ok(())
+                }
+                SubCommand::Gfa => {
+                    let input_gfa = matches.get_one::<PathBuf>("gfa").expect("errored by clap");
+                    let telomeric_repeat = match matches.get_one::<String>("clade") {
+                        Some(clade) => format!("repeats of clade {}", clade),
+                        None => matches
+                            .get_one::<String>("string")
+                            .expect("errored by clap")
+                            .to_string(),
+                    };
+                    let max_gap = matches
+                        .get_one::<usize>("max-gap")
+                        .expect("defaulted by clap");
+                    let min_tract_length = matches
+                        .get_one::<usize>("min-tract-length")
+                        .expect("defaulted by clap");
+                    let max_end_distance = matches
+                        .get_one::<usize>("max-end-distance")
+                        .expect("defaulted by clap");
+                    let annotated = match matches.get_one::<PathBuf>("annotate") {
+                        Some(annotate) => annotate.display().to_string(),
+                        None => "None".into(),
+                    };
+
+                    let log_string = format!(
+                        r#"tidk version: {}
+Log information for output files: printed to STDOUT
+Date: {}
+`tidk gfa` was run with the following parameters:
+    Input GFA: {}
+    Telomeric repeat(s): {}
+    Telomeric tracts: maximum gap {}bp, minimum length {}bp, maximum distance from end {}bp
+    Annotated GFA: {}"#,
+                        crate_version!(),
+                        Local::now().format(DATE_FORMAT_STR),
+                        input_gfa.display(),
+                        telomeric_repeat,
+                        max_gap,
+                        min_tract_length,
+                        max_end_distance,
+                        annotated
+                    );
+
+                    // create file
+                    let log_file_name = "tidk-gfa.log".to_string();
+                    let log_file = std::fs::File::create(&log_file_name)?;
+                    let mut log_file = std::io::LineWriter::new(log_file);
+
+                    writeln!(log_file, "{}", log_string)?;
+
+                    eprintln!("[+]\tLog file written to: {}", log_file_name);
+                    Ok(())
+                }
+                SubCommand::Support => {
+                    let input_bam = matches.get_one::<PathBuf>("bam").expect("errored by clap");
+                    let telomeric_repeat = match matches.get_one::<String>("clade") {
+                        Some(clade) => format!("repeats of clade {}", clade),
+                        None => matches
+                            .get_one::<String>("string")
+                            .expect("errored by clap")
+                            .to_string(),
+                    };
+                    let end_window = matches
+                        .get_one::<usize>("end-window")
+                        .expect("defaulted by clap");
+                    let max_gap = matches
+                        .get_one::<usize>("max-gap")
+                        .expect("defaulted by clap");
+                    let min_tract_length = matches
+                        .get_one::<usize>("min-tract-length")
+                        .expect("defaulted by clap");
+                    let exclude_flags = matches
+                        .get_one::<u16>("exclude-flags")
+                        .expect("defaulted by clap");
+                    let min_mapq = matches
+                        .get_one::<u8>("min-mapq")
+                        .expect("defaulted by clap");
+
+                    let log_string = format!(
+                        r#"tidk version: {}
+Log information for output files: printed to STDOUT
+Date: {}
+`tidk support` was run with the following parameters:
+    Input BAM: {}
+    Telomeric repeat(s): {}
+    Reads aligned within {}bp of an end
+    Read tracts: maximum gap {}bp, minimum length {}bp
+    Excluded flags: {}
+    Minimum mapping quality: {}"#,
+                        crate_version!(),
+                        Local::now().format(DATE_FORMAT_STR),
+                        input_bam.display(),
+                        telomeric_repeat,
+                        end_window,
+                        max_gap,
+                        min_tract_length,
+                        exclude_flags,
+                        min_mapq
                     );
 
+                    // create file
+                    let log_file_name = "tidk-support.log".to_string();
+                    let log_file = std::fs::File::create(&log_file_name)?;
+                    let mut log_file = std::io::LineWriter::new(log_file);
+
+                    writeln!(log_file, "{}", log_string)?;
+
+                    eprintln!("[+]\tLog file written to: {}", log_file_name);
+                    Ok(())
+                }
+                SubCommand::Search => {
+                    let input_fasta = matches
+                        .get_one::<PathBuf>("fasta")
+                        .expect("errored by clap");
+                    let telomeric_repeat = matches
+                        .get_one::<String>("string")
+                        .expect("errored by clap");
+                    let (file_name, log_file_name) = windows_log_files(matches, "search");
+
                     let log_string = format!(
                         r#"tidk version: {}
 Log information for output file: {}
 Date: {}
 `tidk search` was run with the following parameters:
     Input fasta: {}
+    Regions: {}
     Telomeric repeat search string: {}
-    Window size: {}
-                    "#,
+    {}
+    Telomeric tracts: {}
+    Fusions: {}"#,
                         crate_version!(),
                         file_name,
                         Local::now().format(DATE_FORMAT_STR),
                         input_fasta.display(),
+                        regions_log(matches),
                         telomeric_repeat,
-                        window_size
+                        windows_log(matches),
+                        tracts_log(matches),
+                        fusions_log(matches)
                     );
 
                     // create file
-                    let log_file_name =
-                        format!("{}/{}{}", outdir.display(), output.display(), ".log");
                     let log_file = std::fs::File::create(&log_file_name)?;
                     let mut log_file = std::io::LineWriter::new(log_file);
 
                     writeln!(log_file, "{}", log_string)?;
 
-                    Ok(eprintln!("[+]\tLog file written to: {}", log_file_name))
+                    eprintln!("[+]\tLog file written to: {}", log_file_name);
+                    Ok(())
                 }
             }
         } else {
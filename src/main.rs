@@ -1,7 +1,99 @@
 use anyhow::Result;
-use clap::{arg, builder::ArgPredicate, crate_version, value_parser, Arg, Command};
+use clap::{arg, builder::ArgPredicate, crate_version, value_parser, Arg, ArgGroup, Command};
 use std::path::PathBuf;
-use tidk::{clades::CLADES, explore, finder, plot, search, SubCommand};
+use tidk::{batch, clades::CLADES, explore, finder, gfa, plot, qc, search, support, SubCommand};
+
+/// The options for reading BAM or CRAM input, shared by the
+/// subcommands that read sequences.
+fn alignment_args() -> [Arg; 4] {
+    [
+        arg!(--"exclude-flags" [EXCLUDE_FLAGS] "For BAM/CRAM input, skip alignments with any of these flags set [default: secondary and supplementary]")
+            .value_parser(value_parser!(u16))
+            .default_value("2304")
+            .hide_default_value(true),
+        arg!(--"min-mapq" [MIN_MAPQ] "For BAM/CRAM input, skip alignments with a lower mapping quality")
+            .value_parser(value_parser!(u8))
+            .default_value("0"),
+        arg!(--"original-orientation" "For BAM/CRAM input, reverse complement reads aligned to the reverse strand back to their sequenced orientation")
+            .action(clap::ArgAction::SetTrue),
+        arg!(--reference [REFERENCE] "The reference fasta, to decode an aligned CRAM")
+            .value_parser(value_parser!(PathBuf)),
+    ]
+}
+
+/// The options for processing only some regions of the input, shared
+/// by the subcommands that count repeats along records.
+fn region_args() -> [Arg; 2] {
+    [
+        arg!(--regions [REGIONS] "Only process the regions in this BED file")
+            .value_parser(value_parser!(PathBuf)),
+        arg!(--region [REGION] "Only process this region, e.g. chr1:1-50000 (1-based, inclusive). Can be given more than once")
+            .action(clap::ArgAction::Append),
+    ]
+}
+
+/// The options for counting telomeric repeats in windows, and for what
+/// is found from the counts, shared by `tidk find` and `tidk search`.
+/// The output files are required unless one of `required_unless` is given.
+fn window_args(required_unless: &[&'static str]) -> Vec<Arg> {
+    vec![
+        arg!(-w --window [WINDOW] "Window size to calculate telomeric repeat counts in")
+            .value_parser(value_parser!(usize))
+            .default_value("10000"),
+        arg!(--step [STEP] "Step size between the starts of consecutive windows. Less than the window size gives overlapping windows [default: window size]")
+            .value_parser(value_parser!(usize)),
+        arg!(--"ends-only" [LENGTH] "Only count repeats in this many bases (e.g. 100000), or this proportion of the record (e.g. 0.01), at each end of each record")
+            .conflicts_with_all(["regions", "region", "its", "fusions"]),
+        arg!(-o --output <OUTPUT> "Output filename for the TSVs (without extension)")
+            .value_parser(value_parser!(PathBuf))
+            .required_unless_present_any(required_unless),
+        arg!(-d --dir <DIR> "Output directory to write files to")
+            .required_unless_present_any(required_unless)
+            .value_parser(value_parser!(PathBuf)),
+        arg!(--stdout "Write the window counts (TSV or bedgraph) to stdout instead of a file, so --dir and --output are only needed for the other files")
+            .action(clap::ArgAction::SetTrue),
+        arg!(-e --extension [EXTENSION] "The extension, defining the output type of the window counts. A bedgraph sums both strands of all the telomeric repeats in each window")
+            .default_value("tsv")
+            .value_parser(["tsv", "bedgraph"]),
+        arg!(--mismatches [MISMATCHES] "Also count repeat units with up to this many mismatches (Hamming distance)")
+            .value_parser(value_parser!(u8))
+            .conflicts_with("edits"),
+        arg!(--edits [EDITS] "Also count repeat units with up to this many substitutions, insertions or deletions (Levenshtein distance)")
+            .value_parser(value_parser!(u8)),
+        arg!(--"count-mode" [COUNT_MODE] "Which matches are counted: all of them, only those that do not overlap, or only units directly adjacent to another unit")
+            .value_parser(["overlapping", "non-overlapping", "tandem"])
+            .default_value("overlapping"),
+        arg!(--tracts "Also write the contiguous telomeric tracts to a BED file")
+            .action(clap::ArgAction::SetTrue),
+        arg!(--"max-gap" [MAX_GAP] "The largest gap (bp) between two repeat units in the same tract")
+            .value_parser(value_parser!(usize))
+            .default_value("20"),
+        arg!(--"min-tract-length" [MIN_TRACT_LENGTH] "The shortest tract (bp) to report")
+            .value_parser(value_parser!(usize))
+            .default_value("100"),
+        arg!(--agp [AGP] "Add the coordinates of each tract on the scaffolds (or contigs) of this AGP to the tracts BED file, and flag contig telomeres inside a scaffold. Only the tracts BED file is lifted")
+            .value_parser(value_parser!(PathBuf))
+            .requires("tracts"),
+        arg!(--"ends-summary" "Also write a table of the telomeric tract at each end of each record")
+            .action(clap::ArgAction::SetTrue),
+        arg!(--"max-end-distance" [MAX_END_DISTANCE] "The furthest (bp) a tract can be from the end of a record to count as its telomere")
+            .value_parser(value_parser!(usize))
+            .default_value("5000"),
+        arg!(--"min-end-repeats" [MIN_END_REPEATS] "The fewest repeat units in the windows within --max-end-distance of an end of a record to check its orientation")
+            .value_parser(value_parser!(usize))
+            .default_value("10"),
+        arg!(--its "Also write the interstitial telomeric sequences, tracts far from both ends of a record, to a BED file")
+            .action(clap::ArgAction::SetTrue),
+        arg!(--"its-distance" [ITS_DISTANCE] "The closest (bp) an interstitial tract or fusion can be to either end of a record")
+            .value_parser(value_parser!(usize))
+            .default_value("10000"),
+        arg!(--fusions "Also write the inverted junctions of telomeric arrays left by chromosome fusions to a TSV file")
+            .action(clap::ArgAction::SetTrue),
+        arg!(--"fusion-min-repeats" [FUSION_MIN_REPEATS] "The fewest repeat units in the window on each side of a fusion junction")
+            .value_parser(value_parser!(usize))
+            .default_value("10"),
+    ]
+}
 
 fn main() -> Result<()> {
     // command line options
@@ -18,34 +110,21 @@ fn main() -> Result<()> {
                     Arg::new("fasta")
                         .value_name("FASTA")
                         .value_parser(value_parser!(PathBuf))
-                        .help("The input fasta file")
+                        .help("The input fasta or fastq file (which can be gzip or bgzip compressed, or - for stdin), BAM/CRAM or 2bit")
                         .required_unless_present("print")
                 )
-                .arg(
-                    // no longer required.
-                    arg!(-w --window [WINDOW] "Window size to calculate telomeric repeat counts in")
-                        .value_parser(value_parser!(usize))
-                        .default_value("10000")
-                )
                 .arg(
                     arg!(-c --clade <CLADE> "The clade of organism to identify telomeres in")
                         .required_unless_present("print")
                         .value_parser(CLADES.to_owned())
                 )
-                .arg(
-                    arg!(-o --output <OUTPUT> "Output filename for the TSVs (without extension)")
-                        .value_parser(value_parser!(PathBuf))
-                        .required_unless_present("print")
-                )
-                .arg(
-                    arg!(-d --dir <DIR> "Output directory to write files to")
-                        .required_unless_present("print")
-                        .value_parser(value_parser!(PathBuf))
-                )
+                .args(window_args(&["print", "stdout"]))
                 .arg(
                     arg!(-p --print "Print a table of clades, along with their telomeric sequences")
                         .action(clap::ArgAction::SetTrue)
                 )
+                .args(region_args())
+                .args(alignment_args())
                 .arg(
                     arg!(--log "Output a log file")
                         .action(clap::ArgAction::SetTrue)
@@ -59,7 +138,7 @@ fn main() -> Result<()> {
                         .value_name("FASTA")
                         .value_parser(value_parser!(PathBuf))
                         .required(true)
-                        .help("The input fasta file")
+                        .help("The input fasta or fastq file (which can be gzip or bgzip compressed, or - for stdin), BAM/CRAM or 2bit")
                 )
                 .arg(
                     arg!(-l --length [LENGTH] "Length of substring")
@@ -85,7 +164,7 @@ fn main() -> Result<()> {
                         .default_value("100")
                 )
                 .arg(
-                    arg!(--distance [DISTANCE] "The distance from the end of the chromosome as a proportion of chromosome length. Must range from 0-0.5.")
+                    arg!(--distance [DISTANCE] "The distance from the end of the chromosome as a proportion of chromosome length. Must range from 0-0.5. Regions are explored in full")
                         .value_parser(value_parser!(f64))
                         .default_value("0.01")
                 )
@@ -93,6 +172,8 @@ fn main() -> Result<()> {
                     arg!(-v --verbose "Print verbose output.")
                         .action(clap::ArgAction::SetTrue)
                 )
+                .args(region_args())
+                .args(alignment_args())
                 .arg(
                     arg!(--log "Output a log file.")
                         .action(clap::ArgAction::SetTrue)
@@ -106,37 +187,228 @@ fn main() -> Result<()> {
                         .value_name("FASTA")
                         .value_parser(value_parser!(PathBuf))
                         .required(true)
-                        .help("The input fasta file")
+                        .help("The input fasta or fastq file (which can be gzip or bgzip compressed, or - for stdin), BAM/CRAM or 2bit")
                 )
                 .arg(
                     arg!(-s --string <STRING> "The DNA string to query the genome with")
                         .required(true)
                 )
+                .args(window_args(&["stdout"]))
+                .args(region_args())
+                .args(alignment_args())
+                .arg(
+                    arg!(--log "Output a log file")
+                        .action(clap::ArgAction::SetTrue)
+                )
+        )
+        .subcommand(
+            Command::new("qc")
+                .about("Check which records of an assembly are capped by telomeres at one, both or neither end.")
+                .arg(
+                    Arg::new("fasta")
+                        .value_name("FASTA")
+                        .value_parser(value_parser!(PathBuf))
+                        .required(true)
+                        .help("The input fasta or fastq file (which can be gzip or bgzip compressed, or - for stdin), BAM/CRAM or 2bit")
+                )
+                .arg(
+                    arg!(-c --clade [CLADE] "The clade of organism, to use its telomeric repeats")
+                        .value_parser(CLADES.to_owned())
+                )
+                .arg(
+                    arg!(-s --string [STRING] "The telomeric repeat to use")
+                )
+                .group(
+                    ArgGroup::new("telomeric_repeat")
+                        .args(["clade", "string"])
+                        .required(true)
+                )
                 .arg(
-                    arg!(-w --window [WINDOW] "Window size to calculate telomeric repeat counts in")
+                    arg!(--"max-gap" [MAX_GAP] "The largest gap (bp) between two repeat units in the same tract")
                         .value_parser(value_parser!(usize))
-                        .default_value("10000")
+                        .default_value("20")
                 )
                 .arg(
-                    arg!(-o --output <OUTPUT> "Output filename for the TSVs (without extension)")
+                    arg!(--"min-tract-length" [MIN_TRACT_LENGTH] "The shortest tract (bp) that counts as a telomere")
+                        .value_parser(value_parser!(usize))
+                        .default_value("100")
+                )
+                .arg(
+                    arg!(--"max-end-distance" [MAX_END_DISTANCE] "The furthest (bp) a tract can be from the end of a record to count as its telomere")
+                        .value_parser(value_parser!(usize))
+                        .default_value("5000")
+                )
+                .arg(
+                    arg!(--"min-length" [MIN_LENGTH] "Only check records at least this long (bp)")
+                        .value_parser(value_parser!(usize))
+                        .default_value("0")
+                )
+                .args(alignment_args())
+                .arg(
+                    arg!(--log "Output a log file")
+                        .action(clap::ArgAction::SetTrue)
+                )
+        )
+        .subcommand(
+            Command::new("gfa")
+                .about("Find the segments of an assembly graph with telomeres at their ends, and which of those ends are tips of the graph.")
+                .arg(
+                    Arg::new("gfa")
+                        .value_name("GFA")
+                        .value_parser(value_parser!(PathBuf))
                         .required(true)
+                        .help("The input GFA (version 1) graph, which can be gzip or bgzip compressed, or - for stdin")
+                )
+                .arg(
+                    arg!(-c --clade [CLADE] "The clade of organism, to use its telomeric repeats")
+                        .value_parser(CLADES.to_owned())
                 )
                 .arg(
-                    arg!(-d --dir <DIR> "Output directory to write files to")
+                    arg!(-s --string [STRING] "The telomeric repeat to use")
+                )
+                .group(
+                    ArgGroup::new("telomeric_repeat")
+                        .args(["clade", "string"])
                         .required(true)
+                )
+                .arg(
+                    arg!(--"max-gap" [MAX_GAP] "The largest gap (bp) between two repeat units in the same tract")
+                        .value_parser(value_parser!(usize))
+                        .default_value("20")
+                )
+                .arg(
+                    arg!(--"min-tract-length" [MIN_TRACT_LENGTH] "The shortest tract (bp) that counts as a telomere")
+                        .value_parser(value_parser!(usize))
+                        .default_value("100")
+                )
+                .arg(
+                    arg!(--"max-end-distance" [MAX_END_DISTANCE] "The furthest (bp) a tract can be from the end of a segment to count as its telomere")
+                        .value_parser(value_parser!(usize))
+                        .default_value("5000")
+                )
+                .arg(
+                    arg!(-a --annotate [ANNOTATE] "Also write a copy of the graph to this file, with the telomeric segments coloured for Bandage")
                         .value_parser(value_parser!(PathBuf))
                 )
                 .arg(
-                    arg!(-e --extension [EXTENSION] "The extension, defining the output type of the file")
-                        .required_unless_present("print")
-                        .default_value("tsv")
-                        .value_parser(["tsv", "bedgraph"])
+                    arg!(--log "Output a log file")
+                        .action(clap::ArgAction::SetTrue)
+                )
+        )
+        .subcommand(
+            Command::new("support")
+                .about("Count the reads aligned to each end of an assembly that carry telomeric repeats where they hang over the end.")
+                .arg(
+                    Arg::new("bam")
+                        .value_name("BAM")
+                        .value_parser(value_parser!(PathBuf))
+                        .required(true)
+                        .help("The indexed BAM/CRAM of reads aligned to the assembly")
+                )
+                .arg(
+                    arg!(-c --clade [CLADE] "The clade of organism, to use its telomeric repeats")
+                        .value_parser(CLADES.to_owned())
+                )
+                .arg(
+                    arg!(-s --string [STRING] "The telomeric repeat to use")
+                )
+                .group(
+                    ArgGroup::new("telomeric_repeat")
+                        .args(["clade", "string"])
+                        .required(true)
+                )
+                .arg(
+                    arg!(--"end-window" [END_WINDOW] "Only count reads aligned to within this many bp of an end")
+                        .value_parser(value_parser!(usize))
+                        .default_value("1000")
+                )
+                .arg(
+                    arg!(--"max-gap" [MAX_GAP] "The largest gap (bp) between two repeat units in the same tract")
+                        .value_parser(value_parser!(usize))
+                        .default_value("20")
+                )
+                .arg(
+                    arg!(--"min-tract-length" [MIN_TRACT_LENGTH] "The shortest tract (bp) in a read that counts as a telomere")
+                        .value_parser(value_parser!(usize))
+                        .default_value("100")
+                )
+                .arg(
+                    arg!(--"read-tracts" [READ_TRACTS] "Also write the longest tract in each read to this TSV file")
+                        .value_parser(value_parser!(PathBuf))
+                )
+                // reads are always taken as they are aligned here
+                .args(
+                    alignment_args()
+                        .into_iter()
+                        .filter(|arg| arg.get_id() != "original-orientation"),
                 )
                 .arg(
                     arg!(--log "Output a log file")
                         .action(clap::ArgAction::SetTrue)
                 )
         )
+        .subcommand(
+            Command::new("batch")
+                .about("Run find, search or explore on many genomes listed in a manifest, and write one combined table.")
+                .arg(
+                    Arg::new("manifest")
+                        .value_name("MANIFEST")
+                        .value_parser(value_parser!(PathBuf))
+                        .required(true)
+                        .help("A TSV with the sample name, fasta path, and clade (find) or telomeric repeat (search) of each genome")
+                )
+                .arg(
+                    arg!(--mode <MODE> "The subcommand to run on each genome")
+                        .required(true)
+                        .value_parser(["find", "search", "explore"])
+                )
+                .arg(
+                    arg!(-o --output [OUTPUT] "The combined table to write [default: stdout]")
+                        .value_parser(value_parser!(PathBuf))
+                )
+                .arg(
+                    arg!(--threads [THREADS] "The number of threads shared by all the genomes [default: all]")
+                        .value_parser(value_parser!(usize))
+                )
+                // for find and search, the same window options as those subcommands
+                .args(
+                    window_args(&[])
+                        .into_iter()
+                        .filter(|arg| {
+                            ["window", "step", "mismatches", "edits", "count-mode"]
+                                .contains(&arg.get_id().as_str())
+                        }),
+                )
+                .arg(
+                    arg!(-l --length [LENGTH] "For explore, length of substring [default: minimum to maximum]")
+                        .value_parser(value_parser!(usize))
+                )
+                .arg(
+                    arg!(-m --minimum [MINIMUM] "For explore, minimum length of substring")
+                        .value_parser(value_parser!(usize))
+                        .default_value("5")
+                )
+                .arg(
+                    arg!(-x --maximum [MAXIMUM] "For explore, maximum length of substring")
+                        .value_parser(value_parser!(usize))
+                        .default_value("12")
+                )
+                .arg(
+                    arg!(-t --threshold [THRESHOLD] "For explore, positions of repeats are only reported if they occur sequentially in a greater number than the threshold")
+                        .value_parser(value_parser!(i32))
+                        .default_value("100")
+                )
+                .arg(
+                    arg!(--distance [DISTANCE] "For explore, the distance from the end of the chromosome as a proportion of chromosome length. Must range from 0-0.5.")
+                        .value_parser(value_parser!(f64))
+                        .default_value("0.01")
+                )
+                .args(alignment_args())
+                .arg(
+                    arg!(--log "Output a log file")
+                        .action(clap::ArgAction::SetTrue)
+                )
+        )
         .subcommand(
             Command::new("plot")
                 .about("SVG plot of TSV generated from tidk search.")
@@ -175,6 +447,18 @@ fn main() -> Result<()> {
         Some(("search", matches)) => {
             search::search(matches, SubCommand::Search)?;
         }
+        Some(("qc", matches)) => {
+            qc::qc(matches, SubCommand::Qc)?;
+        }
+        Some(("gfa", matches)) => {
+            gfa::gfa(matches, SubCommand::Gfa)?;
+        }
+        Some(("support", matches)) => {
+            support::support(matches, SubCommand::Support)?;
+        }
+        Some(("batch", matches)) => {
+            batch::batch(matches, SubCommand::Batch)?;
+        }
         Some(("plot", matches)) => {
             plot::plot(matches)?;
         }
@@ -0,0 +1,289 @@
+use crate::{clades, reader, tracts, utils, windows, SubCommand};
+use anyhow::{bail, Result};
+use std::fmt::{self, Display};
+use std::path::PathBuf;
+
+/// Which ends of a record have a telomere.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// Telomere to telomere, both ends are capped.
+    T2T,
+    /// Only the 5' end has a telomere.
+    LeftOnly,
+    /// Only the 3' end has a telomere.
+    RightOnly,
+    /// Neither end has a telomere.
+    NoTelomeres,
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Status::T2T => write!(f, "T2T"),
+            Status::LeftOnly => write!(f, "left-only"),
+            Status::RightOnly => write!(f, "right-only"),
+            Status::NoTelomeres => write!(f, "none"),
+        }
+    }
+}
+
+/// Whether the telomeres of a record point the right way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    /// All the telomeres found are on the expected strand.
+    Correct,
+    /// All the telomeres found are on the wrong strand, which
+    /// usually means the record is reverse complemented.
+    Inverted,
+    /// One telomere is on the expected strand, the other is not.
+    Mixed,
+    /// There are no telomeres to check.
+    NotApplicable,
+}
+
+impl Display for Orientation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Orientation::Correct => write!(f, "ok"),
+            Orientation::Inverted => write!(f, "inverted"),
+            Orientation::Mixed => write!(f, "mixed"),
+            Orientation::NotApplicable => write!(f, "NA"),
+        }
+    }
+}
+
+/// The header of the `tidk qc` output.
+pub const QC_HEADER: &str =
+    "id\tlength\tstatus\tleft_tract_length\tright_tract_length\torientation";
+
+/// The telomere QC of a single record.
+#[derive(Debug, Clone)]
+pub struct RecordQc {
+    /// The record.
+    pub id: String,
+    /// The length of the record.
+    pub length: usize,
+    /// The telomeres at the 5' and 3' ends.
+    pub ends: [tracts::TelomereEnd; 2],
+}
+
+impl RecordQc {
+    /// Classify the record by which ends have a telomere.
+    pub fn status(&self) -> Status {
+        match (&self.ends[0].tract, &self.ends[1].tract) {
+            (Some(_), Some(_)) => Status::T2T,
+            (Some(_), None) => Status::LeftOnly,
+            (None, Some(_)) => Status::RightOnly,
+            (None, None) => Status::NoTelomeres,
+        }
+    }
+
+    /// Check the telomeres found are on the strands expected.
+    pub fn orientation(&self) -> Orientation {
+        let oriented: Vec<bool> = self
+            .ends
+            .iter()
+            .filter_map(|e| e.is_correctly_oriented())
+            .collect();
+
+        if oriented.is_empty() {
+            Orientation::NotApplicable
+        } else if oriented.iter().all(|o| *o) {
+            Orientation::Correct
+        } else if oriented.iter().all(|o| !*o) {
+            Orientation::Inverted
+        } else {
+            Orientation::Mixed
+        }
+    }
+
+    /// Format as a row of the `tidk qc` output.
+    pub fn to_tsv(&self) -> String {
+        let tract_length = |end: &tracts::TelomereEnd| end.tract.as_ref().map_or(0, |t| t.length());
+        format!(
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            self.id,
+            self.length,
+            self.status(),
+            tract_length(&self.ends[0]),
+            tract_length(&self.ends[1]),
+            self.orientation()
+        )
+    }
+}
+
+/// The entry point for `tidk qc`.
+///
+/// Classify each record as telomere to telomere, capped at one end
+/// only, or not capped, and check the telomeres point the right way.
+pub fn qc(matches: &clap::ArgMatches, sc: SubCommand) -> Result<()> {
+    let input_fasta = matches
+        .get_one::<PathBuf>("fasta")
+        .expect("errored by clap");
+    let sequences = reader::records(
+        input_fasta,
+        &reader::AlignmentOptions::from_matches(matches),
+    )?;
+
+    let telomeric_repeats = clades::telomeric_repeats_from_matches(matches)?;
+    let telomeric_repeats: Vec<&str> = telomeric_repeats.iter().map(|s| s.as_str()).collect();
+    eprintln!(
+        "[+]\tChecking telomeres using telomeric repeat(s): {}",
+        telomeric_repeats.join(", ")
+    );
+
+    let tract_params = tracts::TractParams {
+        max_gap: *matches
+            .get_one::<usize>("max-gap")
+            .expect("defaulted by clap"),
+        min_length: *matches
+            .get_one::<usize>("min-tract-length")
+            .expect("defaulted by clap"),
+    };
+    let max_end_distance = *matches
+        .get_one::<usize>("max-end-distance")
+        .expect("defaulted by clap");
+    let min_length = *matches
+        .get_one::<usize>("min-length")
+        .expect("defaulted by clap");
+
+    let mut records = Vec::new();
+
+    println!("{}", QC_HEADER);
+    for result in sequences {
+        let record = result?;
+        let id = record.id().to_owned();
+        let length = record.seq().len();
+        if length < min_length {
+            continue;
+        }
+
+        let motifs = windows::find_telomeric_repeats(
+            record.seq(),
+            &telomeric_repeats,
+            utils::Tolerance::Exact,
+        )?;
+        let record_tracts = tracts::call_record_tracts(
+            &id,
+            &telomeric_repeats,
+            &motifs,
+            utils::CountMode::Overlapping,
+            tract_params,
+        );
+        let ends = tracts::telomere_ends(&id, length, &record_tracts, max_end_distance);
+
+        let record_qc = RecordQc { id, length, ends };
+        println!("{}", record_qc.to_tsv());
+        records.push(record_qc);
+    }
+
+    if records.is_empty() {
+        bail!("No records of at least {}bp to check.", min_length)
+    }
+    report_totals(&records);
+
+    // optional log file
+    sc.log(matches)?;
+
+    Ok(())
+}
+
+/// Print the genome wide totals, and any records with telomeres
+/// on the wrong strand.
+fn report_totals(records: &[RecordQc]) {
+    let total = records.len();
+    let count = |status: Status| records.iter().filter(|r| r.status() == status).count();
+    let percent = |n: usize, of: usize| 100.0 * n as f64 / of as f64;
+
+    let capped_ends: usize = records
+        .iter()
+        .map(|r| r.ends.iter().filter(|e| e.tract.is_some()).count())
+        .sum();
+
+    eprintln!("[+]\tRecords checked: {}", total);
+    for status in [
+        Status::T2T,
+        Status::LeftOnly,
+        Status::RightOnly,
+        Status::NoTelomeres,
+    ] {
+        let n = count(status);
+        eprintln!("[+]\t\t{}: {} ({:.1}%)", status, n, percent(n, total));
+    }
+    eprintln!(
+        "[+]\tEnds capped by a telomere: {} of {} ({:.1}%)",
+        capped_ends,
+        2 * total,
+        percent(capped_ends, 2 * total)
+    );
+
+    for record in records {
+        match record.orientation() {
+            Orientation::Inverted | Orientation::Mixed => eprintln!(
+                "[-]\tRecord {} has telomeres pointing the wrong way ({})",
+                record.id,
+                record.orientation()
+            ),
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tracts::{End, Strand, TelomereEnd, Tract};
+
+    fn end(end: End, strand: Option<Strand>) -> TelomereEnd {
+        TelomereEnd {
+            id: "test".into(),
+            end,
+            tract: strand.map(|strand| Tract {
+                id: "test".into(),
+                start: 0,
+                end: 100,
+                strand,
+                telomeric_repeat: "TTAGG".into(),
+                units: 20,
+                purity: 1.0,
+            }),
+            distance: strand.map(|_| 0),
+            counts: None,
+        }
+    }
+
+    fn record(five: Option<Strand>, three: Option<Strand>) -> RecordQc {
+        RecordQc {
+            id: "test".into(),
+            length: 1000,
+            ends: [end(End::Five, five), end(End::Three, three)],
+        }
+    }
+
+    #[test]
+    fn t2t() {
+        let r = record(Some(Strand::Reverse), Some(Strand::Forward));
+        assert_eq!(r.status(), Status::T2T);
+        assert_eq!(r.orientation(), Orientation::Correct);
+        assert_eq!(r.to_tsv(), "test\t1000\tT2T\t100\t100\tok");
+    }
+
+    #[test]
+    fn one_end() {
+        let r = record(None, Some(Strand::Forward));
+        assert_eq!(r.status(), Status::RightOnly);
+        let r = record(Some(Strand::Reverse), None);
+        assert_eq!(r.status(), Status::LeftOnly);
+        let r = record(None, None);
+        assert_eq!(r.status(), Status::NoTelomeres);
+        assert_eq!(r.orientation(), Orientation::NotApplicable);
+    }
+
+    #[test]
+    fn wrong_way() {
+        let r = record(Some(Strand::Forward), Some(Strand::Reverse));
+        assert_eq!(r.orientation(), Orientation::Inverted);
+        let r = record(Some(Strand::Forward), Some(Strand::Forward));
+        assert_eq!(r.orientation(), Orientation::Mixed);
+    }
+}
@@ -0,0 +1,1131 @@
+use crate::tracts::End;
+use crate::twobit::{self, TwoBit};
+use anyhow::{anyhow, bail, Context, Result};
+use bio::alphabets::dna::revcomp;
+use bio::io::{fasta, fastq};
+use rust_htslib::bam::{self, Read as BamRead};
+use rust_htslib::htslib;
+use std::collections::VecDeque;
+use std::ffi::{CStr, CString};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+/// The most threads used to decompress a bgzip file.
+const MAX_DECOMPRESSION_THREADS: usize = 4;
+
+/// The input path that means read from stdin.
+pub const STDIN: &str = "-";
+
+/// Is `path` the stdin placeholder, `-`?
+pub fn is_stdin<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref() == Path::new(STDIN)
+}
+
+/// The compression of an input file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// Plain text.
+    None,
+    /// Gzip, which has to be decompressed in one thread.
+    Gzip,
+    /// Blocked gzip, which can be decompressed in parallel.
+    Bgzip,
+}
+
+impl Compression {
+    /// Tell the compression from the first bytes of a file. Bgzip is
+    /// gzip with an extra field, which has the subfield ID `BC`.
+    pub fn from_magic(bytes: &[u8]) -> Self {
+        match bytes {
+            [0x1f, 0x8b, _, flags, _, _, _, _, _, _, _, _, b'B', b'C', ..] if flags & 0x04 != 0 => {
+                Compression::Bgzip
+            }
+            [0x1f, 0x8b, ..] => Compression::Gzip,
+            _ => Compression::None,
+        }
+    }
+
+    /// Read the first bytes of the file at `path` to tell its compression.
+    pub fn detect<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let mut file =
+            File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        let mut magic = Vec::with_capacity(18);
+        file.by_ref().take(18).read_to_end(&mut magic)?;
+        Ok(Compression::from_magic(&magic))
+    }
+}
+
+/// A gzip or bgzip compressed file, decompressed by htslib as it is read.
+struct Bgzf {
+    inner: *mut htslib::BGZF,
+}
+
+// the handle is only ever used by one thread at a time, and
+// htslib does not tie it to the thread that opened it.
+unsafe impl Send for Bgzf {}
+
+impl Bgzf {
+    /// Open the file at `path`, decompressing on up to `threads`
+    /// threads if it is bgzip compressed. Plain text is passed through
+    /// as it is, and a `path` of `-` reads stdin.
+    fn open(path: &Path, threads: usize) -> Result<Self> {
+        let c_path = CString::new(path.as_os_str().as_bytes())?;
+        let mode = CString::new("r")?;
+        let inner = unsafe { htslib::bgzf_open(c_path.as_ptr(), mode.as_ptr()) };
+        if inner.is_null() {
+            bail!("Failed to open {}", path.display())
+        }
+        let bgzf = Bgzf { inner };
+        // plain gzip can't be decompressed in parallel, htslib ignores this
+        if threads > 1 && unsafe { htslib::bgzf_mt(bgzf.inner, threads as i32, 256) } != 0 {
+            bail!(
+                "Failed to start decompressing {} in parallel",
+                path.display()
+            )
+        }
+        Ok(bgzf)
+    }
+}
+
+impl Read for Bgzf {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let bytes = unsafe {
+            htslib::bgzf_read(
+                self.inner,
+                buf.as_mut_ptr() as *mut std::os::raw::c_void,
+                buf.len() as _,
+            )
+        };
+        if bytes < 0 {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Failed to decompress input",
+            ))
+        } else {
+            Ok(bytes as usize)
+        }
+    }
+}
+
+impl Drop for Bgzf {
+    fn drop(&mut self) {
+        unsafe {
+            htslib::bgzf_close(self.inner);
+        }
+    }
+}
+
+/// The number of threads used to decompress bgzip, BAM or CRAM input.
+pub(crate) fn decompression_threads() -> usize {
+    std::thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(MAX_DECOMPRESSION_THREADS)
+}
+
+/// Open the file at `path` for reading, decompressing it on the fly
+/// if it is gzip or bgzip compressed. A `path` of `-` reads stdin.
+pub fn open<P: AsRef<Path>>(path: P) -> Result<Box<dyn Read + Send>> {
+    let path = path.as_ref();
+    // stdin can't be peeked at and reopened, so htslib tells the
+    // compression as it reads
+    if is_stdin(path) {
+        return Ok(Box::new(Bgzf::open(path, decompression_threads())?));
+    }
+    Ok(match Compression::detect(path)? {
+        Compression::None => Box::new(File::open(path)?),
+        Compression::Gzip => Box::new(Bgzf::open(path, 1)?),
+        Compression::Bgzip => Box::new(Bgzf::open(path, decompression_threads())?),
+    })
+}
+
+/// The format of an input file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Fasta,
+    Fastq,
+    Bam,
+    Cram,
+    TwoBit,
+}
+
+impl Format {
+    /// Tell the format from the first bytes of the (decompressed) file.
+    /// Empty files are read as fasta, which has no records.
+    pub fn from_start(bytes: &[u8]) -> Result<Self> {
+        match bytes {
+            _ if twobit::is_twobit(bytes) => Ok(Format::TwoBit),
+            [b'>', ..] | [] => Ok(Format::Fasta),
+            [b'@', ..] => Ok(Format::Fastq),
+            [b'B', b'A', b'M', 1, ..] => Ok(Format::Bam),
+            [b'C', b'R', b'A', b'M', ..] => Ok(Format::Cram),
+            [b, ..] => bail!(
+                "Input is not fasta, fastq, BAM, CRAM or 2bit, it starts with {:?}",
+                *b as char
+            ),
+        }
+    }
+}
+
+/// Which alignments are read from BAM or CRAM input, and how.
+#[derive(Debug, Clone)]
+pub struct AlignmentOptions {
+    /// Skip alignments with any of these flags set.
+    pub exclude_flags: u16,
+    /// Skip alignments with a lower mapping quality.
+    pub min_mapq: u8,
+    /// Reverse complement reads aligned to the reverse strand,
+    /// back to the orientation they were sequenced in.
+    pub original_orientation: bool,
+    /// The reference needed to decode an aligned CRAM.
+    pub reference: Option<PathBuf>,
+}
+
+impl Default for AlignmentOptions {
+    /// Skip secondary and supplementary alignments, which repeat
+    /// (part of) a read, and keep the rest as they are stored.
+    fn default() -> Self {
+        AlignmentOptions {
+            exclude_flags: 0x900,
+            min_mapq: 0,
+            original_orientation: false,
+            reference: None,
+        }
+    }
+}
+
+impl AlignmentOptions {
+    /// Get the alignment options from the command line.
+    pub fn from_matches(matches: &clap::ArgMatches) -> Self {
+        AlignmentOptions {
+            exclude_flags: *matches
+                .get_one::<u16>("exclude-flags")
+                .expect("defaulted by clap"),
+            min_mapq: *matches
+                .get_one::<u8>("min-mapq")
+                .expect("defaulted by clap"),
+            // not every subcommand has this option
+            original_orientation: matches
+                .try_get_one::<bool>("original-orientation")
+                .ok()
+                .flatten()
+                .copied()
+                .unwrap_or(false),
+            reference: matches.get_one::<PathBuf>("reference").cloned(),
+        }
+    }
+
+    /// Should this alignment be read?
+    pub(crate) fn keep(&self, record: &bam::Record) -> bool {
+        record.flags() & self.exclude_flags == 0
+            && record.mapq() >= self.min_mapq
+            && record.seq_len() > 0
+    }
+}
+
+/// The reads of a BAM or CRAM file, as fasta records.
+struct Alignments {
+    reader: bam::Reader,
+    record: bam::Record,
+    options: AlignmentOptions,
+}
+
+impl Alignments {
+    fn open(path: &Path, options: AlignmentOptions) -> Result<Self> {
+        let mut reader = bam::Reader::from_path(path)
+            .with_context(|| format!("Failed to read alignments from {}", path.display()))?;
+        reader.set_threads(decompression_threads())?;
+        if let Some(reference) = &options.reference {
+            reader.set_reference(reference)?;
+        }
+        Ok(Alignments {
+            reader,
+            record: bam::Record::new(),
+            options,
+        })
+    }
+}
+
+impl Iterator for Alignments {
+    type Item = Result<fasta::Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.reader.read(&mut self.record)? {
+                Ok(()) => (),
+                Err(e) => return Some(Err(e).context("Failed to parse alignment")),
+            }
+            if !self.options.keep(&self.record) {
+                continue;
+            }
+
+            let id = String::from_utf8_lossy(self.record.qname()).to_string();
+            let mut seq = self.record.seq().as_bytes();
+            if self.options.original_orientation && self.record.flags() & 0x10 != 0 {
+                seq = revcomp(seq);
+            }
+            return Some(Ok(fasta::Record::with_attrs(&id, None, &seq)));
+        }
+    }
+}
+
+/// The records of a fasta, fastq, BAM, CRAM or 2bit file. Fastq reads
+/// and alignments are returned as fasta records, without their base
+/// qualities.
+pub type Records = Box<dyn Iterator<Item = Result<fasta::Record>> + Send>;
+
+/// Read the records of the file at `path`, which can be fasta or fastq
+/// (plain text, gzip or bgzip compressed), BAM, CRAM or 2bit. The alignment
+/// options are only used for BAM and CRAM. A `path` of `-` reads fasta
+/// or fastq from stdin.
+pub fn records<P: AsRef<Path>>(path: P, options: &AlignmentOptions) -> Result<Records> {
+    let path = path.as_ref();
+    let reader =
+        open(path).with_context(|| format!("Failed to read sequences from {}", path.display()))?;
+    let mut reader = BufReader::new(reader);
+    let start = reader.fill_buf()?;
+
+    Ok(match Format::from_start(&start[..start.len().min(4)])? {
+        Format::Fasta => Box::new(
+            fasta::Reader::from_bufread(reader)
+                .records()
+                .map(|r| r.context("Failed to parse fasta record")),
+        ),
+        Format::Fastq => Box::new(fastq::Reader::from_bufread(reader).records().map(|r| {
+            let read = r.context("Failed to parse fastq record")?;
+            Ok(fasta::Record::with_attrs(
+                read.id(),
+                read.desc(),
+                read.seq(),
+            ))
+        })),
+        // the start of stdin has been read already
+        Format::Bam | Format::Cram if is_stdin(path) => bail!(
+            "BAM and CRAM can't be read from stdin, give the path to the file, or pipe through `samtools fasta`"
+        ),
+        // htslib reads these itself
+        Format::Bam | Format::Cram => Box::new(Alignments::open(path, options.clone())?),
+        Format::TwoBit if is_stdin(path) => {
+            bail!("2bit can't be read from stdin, give the path to the file")
+        }
+        Format::TwoBit => Box::new(TwoBit::open(path)?.into_records()),
+    })
+}
+
+/// A part of a record to process, from `--region` or `--regions`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    /// The record the region is on.
+    pub id: String,
+    /// 0-based start of the region.
+    pub start: usize,
+    /// Exclusive end of the region, or `None` for the end of the record.
+    pub end: Option<usize>,
+}
+
+impl Region {
+    /// Parse a region string as samtools does: `chr1` for a whole record,
+    /// `chr1:1001` from 1001 to the end, or `chr1:1001-2000`. Positions
+    /// are 1-based and inclusive, and can have commas in.
+    pub fn parse(region: &str) -> Result<Self> {
+        let whole = Region {
+            id: region.to_string(),
+            start: 0,
+            end: None,
+        };
+        // record names can have colons in too, so if what follows the
+        // last one isn't a range, it's all the name
+        let Some((id, range)) = region.rsplit_once(':') else {
+            return Ok(whole);
+        };
+        let position = |p: &str| p.replace(',', "").parse::<usize>();
+        let (start, end) = match range.split_once('-') {
+            Some((start, end)) => match (position(start), position(end)) {
+                (Ok(start), Ok(end)) => (start, Some(end)),
+                _ => return Ok(whole),
+            },
+            None => match position(range) {
+                Ok(start) => (start, None),
+                _ => return Ok(whole),
+            },
+        };
+        if id.is_empty() || start == 0 || end.is_some_and(|end| end < start) {
+            bail!(
+                "{} is not a valid region, it should look like chr1:1-50000",
+                region
+            )
+        }
+        Ok(Region {
+            id: id.to_string(),
+            start: start - 1,
+            end,
+        })
+    }
+
+    /// Parse a line of a BED file, which is 0-based and half open.
+    pub fn from_bed(line: &str) -> Result<Self> {
+        let columns: Vec<&str> = line.split('\t').collect();
+        let [id, start, end, ..] = columns[..] else {
+            bail!("BED line has fewer than three columns: {}", line)
+        };
+        let start = start
+            .trim()
+            .parse::<usize>()
+            .with_context(|| format!("Bad start in BED line: {}", line))?;
+        let end = end
+            .trim()
+            .parse::<usize>()
+            .with_context(|| format!("Bad end in BED line: {}", line))?;
+        if end <= start {
+            bail!("BED line ends before it starts: {}", line)
+        }
+        Ok(Region {
+            id: id.to_string(),
+            start,
+            end: Some(end),
+        })
+    }
+
+    /// The start and end of this region on a record of `record_length`
+    /// bases. Regions running past the end of the record are cut short.
+    fn bounds(&self, record_length: usize) -> Result<(usize, usize)> {
+        let end = self.end.unwrap_or(record_length).min(record_length);
+        if self.start >= end {
+            bail!(
+                "Region {} starts after the end of {}, which is {}bp",
+                self,
+                self.id,
+                record_length
+            )
+        }
+        Ok((self.start, end))
+    }
+}
+
+impl std::fmt::Display for Region {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.end {
+            Some(end) => write!(f, "{}:{}-{}", self.id, self.start + 1, end),
+            None => write!(f, "{}:{}", self.id, self.start + 1),
+        }
+    }
+}
+
+/// Read the regions in a BED file. Blank lines, comments and
+/// `track` or `browser` lines are skipped.
+pub fn read_bed<P: AsRef<Path>>(path: P) -> Result<Vec<Region>> {
+    let path = path.as_ref();
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut regions = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.trim().is_empty()
+            || line.starts_with('#')
+            || line.starts_with("track")
+            || line.starts_with("browser")
+        {
+            continue;
+        }
+        regions.push(Region::from_bed(&line)?);
+    }
+    Ok(regions)
+}
+
+/// Get the regions from the command line, those in the `--regions`
+/// BED file followed by any `--region` strings. No regions means the
+/// whole of every record.
+pub fn regions_from_matches(matches: &clap::ArgMatches) -> Result<Vec<Region>> {
+    let mut regions = match matches.get_one::<PathBuf>("regions") {
+        Some(bed) => read_bed(bed)?,
+        None => Vec::new(),
+    };
+    if let Some(strings) = matches.get_many::<String>("region") {
+        for region in strings {
+            regions.push(Region::parse(region)?);
+        }
+    }
+    Ok(regions)
+}
+
+/// A record, or the part of it in a region.
+#[derive(Debug, Clone)]
+pub struct RegionRecord {
+    /// The sequence of the region, with the id of the whole record.
+    pub record: fasta::Record,
+    /// Where the region starts on the record.
+    pub offset: usize,
+    /// The length of the whole record.
+    pub record_length: usize,
+}
+
+impl RegionRecord {
+    /// The whole of a record.
+    pub fn whole(record: fasta::Record) -> Self {
+        let record_length = record.seq().len();
+        RegionRecord {
+            record,
+            offset: 0,
+            record_length,
+        }
+    }
+
+    /// Cut a region out of a record.
+    fn cut(record: &fasta::Record, region: &Region) -> Result<Self> {
+        let record_length = record.seq().len();
+        let (start, end) = region.bounds(record_length)?;
+        Ok(RegionRecord {
+            record: fasta::Record::with_attrs(
+                record.id(),
+                record.desc(),
+                &record.seq()[start..end],
+            ),
+            offset: start,
+            record_length,
+        })
+    }
+
+    /// Cut both ends out of a record.
+    fn ends(record: &fasta::Record, end_length: EndLength) -> [Self; 2] {
+        let record_length = record.seq().len();
+        let length = end_length.of(record_length);
+        let end = |offset: usize| RegionRecord {
+            record: fasta::Record::with_attrs(
+                record.id(),
+                record.desc(),
+                &record.seq()[offset..offset + length],
+            ),
+            offset,
+            record_length,
+        };
+        [end(0), end(record_length - length)]
+    }
+
+    /// The ends of a record to scan, merged back into the whole record
+    /// if they meet or overlap, so that no base is scanned twice.
+    pub fn merge_ends(ends: [Self; 2]) -> Vec<Self> {
+        let [five, three] = ends;
+        let five_end = five.record.seq().len();
+        if five_end < three.offset {
+            return vec![five, three];
+        }
+        let mut seq = five.record.seq().to_vec();
+        seq.extend_from_slice(&three.record.seq()[five_end - three.offset..]);
+        vec![RegionRecord {
+            record: fasta::Record::with_attrs(five.record.id(), five.record.desc(), &seq),
+            offset: 0,
+            record_length: five.record_length,
+        }]
+    }
+
+    /// Does the region reach this end of the record?
+    pub fn reaches(&self, end: End) -> bool {
+        match end {
+            End::Five => self.offset == 0,
+            End::Three => self.offset + self.record.seq().len() == self.record_length,
+        }
+    }
+}
+
+/// How much of each end of a record to read.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EndLength {
+    /// A proportion of the length of the record.
+    Proportion(f64),
+    /// A number of bases.
+    Bases(usize),
+}
+
+impl EndLength {
+    /// Parse an end length from the command line, a whole number of
+    /// bases (e.g. `100000`) or a proportion of at most half the
+    /// record (e.g. `0.01`).
+    pub fn parse(length: &str) -> Result<Self> {
+        if let Ok(bases) = length.parse::<usize>() {
+            if bases > 0 {
+                return Ok(EndLength::Bases(bases));
+            }
+        } else if let Ok(p) = length.parse::<f64>() {
+            if p > 0.0 && p <= 0.5 {
+                return Ok(EndLength::Proportion(p));
+            }
+        }
+        bail!(
+            "The end length should be a number of bases, or a proportion of the record up to 0.5, not {}",
+            length
+        )
+    }
+
+    /// The number of bases at each end of a record of `record_length`
+    /// bases. The ends of a short record can overlap, but never run
+    /// past the record.
+    pub fn of(&self, record_length: usize) -> usize {
+        let length = match self {
+            EndLength::Proportion(p) => (record_length as f64 * p).ceil() as usize,
+            EndLength::Bases(bases) => *bases,
+        };
+        length.min(record_length)
+    }
+}
+
+impl std::fmt::Display for EndLength {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EndLength::Proportion(p) => write!(f, "{} of the bases", p),
+            EndLength::Bases(bases) => write!(f, "{} bases", bases),
+        }
+    }
+}
+
+/// An input that any part of a record can be read from, without
+/// reading the rest of the file.
+trait RandomAccess: Send {
+    /// The names of the records, in the order they are in the file.
+    fn ids(&self) -> Result<Vec<String>>;
+
+    /// The length of record `id`, or `None` if it's not in the file.
+    fn record_length(&self, id: &str) -> Result<Option<usize>>;
+
+    /// Read the bases from `start` up to `end` of record `id`.
+    fn fetch_range(&self, id: &str, start: usize, end: usize) -> Result<Vec<u8>>;
+
+    /// Fetch a region, as a record with its position on the whole record.
+    fn fetch(&self, region: &Region) -> Result<RegionRecord> {
+        let Some(record_length) = self.record_length(&region.id)? else {
+            bail!(
+                "There is no record {} in the index, for region {}",
+                region.id,
+                region
+            )
+        };
+        let (start, end) = region.bounds(record_length)?;
+        Ok(RegionRecord {
+            record: fasta::Record::with_attrs(
+                &region.id,
+                None,
+                &self.fetch_range(&region.id, start, end)?,
+            ),
+            offset: start,
+            record_length,
+        })
+    }
+
+    /// Fetch both ends of record `id`, reading nothing in between.
+    fn fetch_ends(&self, id: &str, end_length: EndLength) -> Result<[RegionRecord; 2]> {
+        let record_length = self
+            .record_length(id)?
+            .with_context(|| format!("There is no record {} in the index", id))?;
+        let length = end_length.of(record_length);
+        let end = |offset: usize| -> Result<RegionRecord> {
+            Ok(RegionRecord {
+                record: fasta::Record::with_attrs(
+                    id,
+                    None,
+                    &self.fetch_range(id, offset, offset + length)?,
+                ),
+                offset,
+                record_length,
+            })
+        };
+        Ok([end(0)?, end(record_length - length)?])
+    }
+}
+
+/// Open the file at `path` for random access, if it is 2bit or
+/// an indexed fasta.
+fn random_access(path: &Path) -> Result<Option<Box<dyn RandomAccess>>> {
+    if is_stdin(path) {
+        return Ok(None);
+    }
+    let mut start = Vec::with_capacity(4);
+    File::open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?
+        .take(4)
+        .read_to_end(&mut start)?;
+    if twobit::is_twobit(&start) {
+        return Ok(Some(Box::new(TwoBit::open(path)?)));
+    }
+    Ok(Faidx::open(path)?.map(|faidx| Box::new(faidx) as Box<dyn RandomAccess>))
+}
+
+impl RandomAccess for TwoBit {
+    fn ids(&self) -> Result<Vec<String>> {
+        Ok(TwoBit::ids(self).to_vec())
+    }
+
+    fn record_length(&self, id: &str) -> Result<Option<usize>> {
+        TwoBit::record_length(self, id)
+    }
+
+    fn fetch_range(&self, id: &str, start: usize, end: usize) -> Result<Vec<u8>> {
+        TwoBit::fetch(self, id, start, end)
+    }
+}
+
+/// An indexed fasta, plain text or bgzip compressed, that htslib
+/// fetches regions from without reading the rest of the file.
+struct Faidx {
+    inner: *mut htslib::faidx_t,
+}
+
+// as for `Bgzf`, only one thread uses the index at a time.
+unsafe impl Send for Faidx {}
+
+impl Faidx {
+    /// Open the index of the fasta at `path`, if it has one. Bgzip
+    /// compressed fasta needs a `.gzi` index as well as the `.fai`.
+    fn open(path: &Path) -> Result<Option<Self>> {
+        let index = |extension: &str| {
+            let mut index = path.as_os_str().to_owned();
+            index.push(extension);
+            PathBuf::from(index).exists()
+        };
+        let indexed = match Compression::detect(path)? {
+            Compression::None => index(".fai"),
+            Compression::Bgzip => index(".fai") && index(".gzi"),
+            Compression::Gzip => false,
+        };
+        if !indexed {
+            return Ok(None);
+        }
+
+        let c_path = CString::new(path.as_os_str().as_bytes())?;
+        // with no flags, htslib won't try to build a missing index
+        let inner =
+            unsafe { htslib::fai_load3(c_path.as_ptr(), std::ptr::null(), std::ptr::null(), 0) };
+        if inner.is_null() {
+            bail!("Failed to load the index of {}", path.display())
+        }
+        Ok(Some(Faidx { inner }))
+    }
+}
+
+impl RandomAccess for Faidx {
+    fn ids(&self) -> Result<Vec<String>> {
+        let n = unsafe { htslib::faidx_nseq(self.inner) };
+        (0..n)
+            .map(|i| {
+                let id = unsafe { htslib::faidx_iseq(self.inner, i) };
+                if id.is_null() {
+                    bail!("Failed to read record {} of the index", i)
+                }
+                Ok(unsafe { CStr::from_ptr(id) }.to_string_lossy().to_string())
+            })
+            .collect()
+    }
+
+    fn record_length(&self, id: &str) -> Result<Option<usize>> {
+        let c_id = CString::new(id)?;
+        let length = unsafe { htslib::faidx_seq_len(self.inner, c_id.as_ptr()) };
+        Ok((length >= 0).then_some(length as usize))
+    }
+
+    fn fetch_range(&self, id: &str, start: usize, end: usize) -> Result<Vec<u8>> {
+        if start >= end {
+            return Ok(Vec::new());
+        }
+        let c_id = CString::new(id)?;
+        let mut length: htslib::hts_pos_t = 0;
+        // htslib takes an inclusive end
+        let seq = unsafe {
+            htslib::faidx_fetch_seq64(
+                self.inner,
+                c_id.as_ptr(),
+                start as htslib::hts_pos_t,
+                (end - 1) as htslib::hts_pos_t,
+                &mut length,
+            )
+        };
+        if seq.is_null() || length < 0 {
+            bail!("Failed to fetch {}:{}-{}", id, start + 1, end)
+        }
+        let bytes =
+            unsafe { std::slice::from_raw_parts(seq as *const u8, length as usize) }.to_vec();
+        unsafe { htslib::hts_free(seq as *mut std::os::raw::c_void) };
+        Ok(bytes)
+    }
+}
+
+impl Drop for Faidx {
+    fn drop(&mut self) {
+        unsafe {
+            htslib::fai_destroy(self.inner);
+        }
+    }
+}
+
+/// Cuts the regions out of the records of a file, as it is read
+/// from start to end.
+struct StreamedRegions {
+    records: Records,
+    regions: Vec<Region>,
+    /// Which of the regions have had their record read.
+    found: Vec<bool>,
+    pending: VecDeque<Result<RegionRecord>>,
+}
+
+impl Iterator for StreamedRegions {
+    type Item = Result<RegionRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(region_record) = self.pending.pop_front() {
+                return Some(region_record);
+            }
+            match self.records.next() {
+                Some(Ok(record)) => {
+                    for (region, found) in self.regions.iter().zip(self.found.iter_mut()) {
+                        if region.id == record.id() {
+                            *found = true;
+                            self.pending.push_back(RegionRecord::cut(&record, region));
+                        }
+                    }
+                }
+                Some(Err(e)) => return Some(Err(e)),
+                None => {
+                    // all the records are read, so these aren't in the file
+                    let missing = self.found.iter().position(|found| !found)?;
+                    self.found[missing] = true;
+                    let region = &self.regions[missing];
+                    return Some(Err(anyhow!(
+                        "There is no record {} in the input, for region {}",
+                        region.id,
+                        region
+                    )));
+                }
+            }
+        }
+    }
+}
+
+/// The regions of the file at `path` to process, or the whole of every
+/// record if there are no `regions`. The regions are fetched from 2bit
+/// or an indexed fasta (with a `.fai`, and a `.gzi` if it is bgzip
+/// compressed) without reading the rest of the file, and otherwise cut
+/// out of the records as the file is read.
+pub fn region_records<P: AsRef<Path>>(
+    path: P,
+    regions: &[Region],
+    options: &AlignmentOptions,
+) -> Result<Box<dyn Iterator<Item = Result<RegionRecord>> + Send>> {
+    let path = path.as_ref();
+    if regions.is_empty() {
+        return Ok(Box::new(
+            records(path, options)?.map(|r| r.map(RegionRecord::whole)),
+        ));
+    }
+    if let Some(indexed) = random_access(path)? {
+        let regions = regions.to_vec();
+        return Ok(Box::new(
+            regions
+                .into_iter()
+                .map(move |region| indexed.fetch(&region)),
+        ));
+    }
+    Ok(Box::new(StreamedRegions {
+        records: records(path, options)?,
+        regions: regions.to_vec(),
+        found: vec![false; regions.len()],
+        pending: VecDeque::new(),
+    }))
+}
+
+/// The 5' and 3' ends of each record of the file at `path`. If the file
+/// is 2bit or an indexed fasta, only the ends are read from the file,
+/// otherwise each whole record is read and then cut down to its ends.
+pub fn end_records<P: AsRef<Path>>(
+    path: P,
+    end_length: EndLength,
+    options: &AlignmentOptions,
+) -> Result<Box<dyn Iterator<Item = Result<[RegionRecord; 2]>> + Send>> {
+    let path = path.as_ref();
+    if let Some(indexed) = random_access(path)? {
+        let ids = indexed.ids()?;
+        return Ok(Box::new(
+            ids.into_iter()
+                .map(move |id| indexed.fetch_ends(&id, end_length)),
+        ));
+    }
+    Ok(Box::new(records(path, options)?.map(move |r| {
+        r.map(|record| RegionRecord::ends(&record, end_length))
+    })))
+}
+
+/// The ends of each record of the file at `path`, as regions to scan,
+/// with the two ends of a short record merged back into one region.
+pub fn end_region_records<P: AsRef<Path>>(
+    path: P,
+    end_length: EndLength,
+    options: &AlignmentOptions,
+) -> Result<Box<dyn Iterator<Item = Result<RegionRecord>> + Send>> {
+    Ok(Box::new(end_records(path, end_length, options)?.flat_map(
+        |ends| {
+            match ends {
+                Ok(ends) => RegionRecord::merge_ends(ends)
+                    .into_iter()
+                    .map(Ok)
+                    .collect::<Vec<_>>(),
+                Err(e) => vec![Err(e)],
+            }
+        },
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::TempFile;
+    use rust_htslib::bgzf;
+    use std::io::Write;
+
+    #[test]
+    fn magic() {
+        assert_eq!(Compression::from_magic(b">chr1\nACGT"), Compression::None);
+        assert_eq!(
+            Compression::from_magic(&[0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 3]),
+            Compression::Gzip
+        );
+        assert_eq!(
+            Compression::from_magic(&[
+                0x1f, 0x8b, 8, 4, 0, 0, 0, 0, 0, 0xff, 6, 0, b'B', b'C', 2, 0, 0x1b, 0
+            ]),
+            Compression::Bgzip
+        );
+        assert_eq!(Compression::from_magic(&[]), Compression::None);
+    }
+
+    #[test]
+    fn read_bgzip() {
+        let file = TempFile::new("reader.fa.gz");
+        let path = &file.path;
+        {
+            let mut writer = bgzf::Writer::from_path(path).unwrap();
+            writer
+                .write_all(b">chr1\nTTAGGTTAGG\nCCTAA\n>chr2\nACGT\n")
+                .unwrap();
+        }
+        assert_eq!(Compression::detect(path).unwrap(), Compression::Bgzip);
+
+        let records: Vec<fasta::Record> = records(path, &AlignmentOptions::default())
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].seq(), b"TTAGGTTAGGCCTAA");
+        assert_eq!(records[1].id(), "chr2");
+    }
+
+    #[test]
+    fn read_fastq() {
+        let file = TempFile::new("reader.fq");
+        let path = &file.path;
+        std::fs::write(
+            path,
+            b"@read1 hifi\nTTAGGTTAGG\n+\nIIIIIIIIII\n@read2\nACGT\n+\nIIII\n",
+        )
+        .unwrap();
+
+        let records: Vec<fasta::Record> = records(path, &AlignmentOptions::default())
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].id(), "read1");
+        assert_eq!(records[0].desc(), Some("hifi"));
+        assert_eq!(records[0].seq(), b"TTAGGTTAGG");
+        assert_eq!(records[1].seq(), b"ACGT");
+    }
+
+    #[test]
+    fn read_bam() {
+        let file = TempFile::new("reader.bam");
+        let path = &file.path;
+        {
+            let mut writer =
+                bam::Writer::from_path(path, &bam::Header::new(), bam::Format::Bam).unwrap();
+            for (qname, flags, mapq) in [
+                (&b"read1"[..], 4, 255),
+                (b"read2", 0x10, 60),
+                (b"read3", 0x100, 60),
+                (b"read4", 0, 1),
+            ] {
+                let mut record = bam::Record::new();
+                record.set(qname, None, b"TTAGGC", &[30; 6]);
+                record.set_flags(flags);
+                record.set_mapq(mapq);
+                writer.write(&record).unwrap();
+            }
+        }
+
+        let read = |options: &AlignmentOptions| -> Vec<fasta::Record> {
+            records(path, options)
+                .unwrap()
+                .map(|r| r.unwrap())
+                .collect()
+        };
+
+        // the secondary alignment is skipped by default
+        let all = read(&AlignmentOptions::default());
+        let ids: Vec<&str> = all.iter().map(|r| r.id()).collect();
+        assert_eq!(ids, vec!["read1", "read2", "read4"]);
+        assert_eq!(all[1].seq(), b"TTAGGC");
+
+        let filtered = read(&AlignmentOptions {
+            min_mapq: 10,
+            original_orientation: true,
+            ..Default::default()
+        });
+
+        assert_eq!(filtered.len(), 2);
+        assert_eq!(filtered[1].id(), "read2");
+        assert_eq!(filtered[1].seq(), b"GCCTAA");
+    }
+
+    #[test]
+    fn format() {
+        assert_eq!(Format::from_start(b">chr1").unwrap(), Format::Fasta);
+        assert_eq!(Format::from_start(b"@read1").unwrap(), Format::Fastq);
+        assert_eq!(Format::from_start(b"BAM\x01").unwrap(), Format::Bam);
+        assert_eq!(Format::from_start(b"CRAM").unwrap(), Format::Cram);
+        assert_eq!(Format::from_start(b"").unwrap(), Format::Fasta);
+        assert_eq!(
+            Format::from_start(&[0x43, 0x27, 0x41, 0x1a]).unwrap(),
+            Format::TwoBit
+        );
+        assert!(Format::from_start(b"ACGT").is_err());
+    }
+
+    #[test]
+    fn regions() {
+        let region = |id: &str, start, end| Region {
+            id: id.into(),
+            start,
+            end,
+        };
+        assert_eq!(Region::parse("chr1").unwrap(), region("chr1", 0, None));
+        assert_eq!(
+            Region::parse("chr1:1-50,000").unwrap(),
+            region("chr1", 0, Some(50000))
+        );
+        assert_eq!(
+            Region::parse("chr1:101").unwrap(),
+            region("chr1", 100, None)
+        );
+        assert_eq!(
+            Region::parse("chr1:alt").unwrap(),
+            region("chr1:alt", 0, None)
+        );
+        assert!(Region::parse("chr1:0-10").is_err());
+        assert!(Region::parse("chr1:20-10").is_err());
+        assert_eq!(
+            Region::from_bed("chr2\t100\t200\tname").unwrap(),
+            region("chr2", 100, Some(200))
+        );
+        assert!(Region::from_bed("chr2\t100").is_err());
+        assert_eq!(region("chr1", 0, Some(50000)).to_string(), "chr1:1-50000");
+    }
+
+    #[test]
+    fn read_regions() {
+        let mut file = TempFile::new("regions.fa");
+        let fai = file.companion(".fai");
+        let path = &file.path;
+        std::fs::write(path, ">chr1\nAAAAACCCCC\nGGGGG\n>chr2\nTTTTT\n").unwrap();
+        let regions = vec![
+            Region::parse("chr1:4-12").unwrap(),
+            Region::parse("chr2").unwrap(),
+        ];
+        let cut = |path: &Path| -> Vec<(String, Vec<u8>, usize, usize)> {
+            region_records(path, &regions, &AlignmentOptions::default())
+                .unwrap()
+                .map(|r| {
+                    let r = r.unwrap();
+                    let id = r.record.id().to_string();
+                    (id, r.record.seq().to_vec(), r.offset, r.record_length)
+                })
+                .collect()
+        };
+        let expected = vec![
+            ("chr1".to_string(), b"AACCCCCGG".to_vec(), 3, 15),
+            ("chr2".to_string(), b"TTTTT".to_vec(), 0, 5),
+        ];
+
+        // read from start to end
+        let streamed = cut(path);
+        // and fetched through the index
+        std::fs::write(&fai, "chr1\t15\t6\t10\t11\nchr2\t5\t29\t5\t6\n").unwrap();
+        let fetched = cut(path);
+
+        let missing = region_records(
+            path,
+            &[Region::parse("chr3").unwrap()],
+            &AlignmentOptions::default(),
+        )
+        .unwrap()
+        .next()
+        .unwrap();
+
+        assert_eq!(streamed, expected);
+        assert_eq!(fetched, expected);
+        assert!(missing.is_err());
+    }
+
+    #[test]
+    fn read_ends() {
+        let mut file = TempFile::new("ends.fa");
+        let fai = file.companion(".fai");
+        let path = &file.path;
+        std::fs::write(path, ">chr1\nAAAAACCCCC\nGGGGG\n>chr2\nTT\n").unwrap();
+        let ends = |path: &Path| -> Vec<(Vec<u8>, usize, Vec<u8>, usize)> {
+            end_records(path, EndLength::Bases(4), &AlignmentOptions::default())
+                .unwrap()
+                .map(|r| {
+                    let [five, three] = r.unwrap();
+                    (
+                        five.record.seq().to_vec(),
+                        five.offset,
+                        three.record.seq().to_vec(),
+                        three.offset,
+                    )
+                })
+                .collect()
+        };
+        // the ends of chr2 are the whole record
+        let expected = vec![
+            (b"AAAA".to_vec(), 0, b"GGGG".to_vec(), 11),
+            (b"TT".to_vec(), 0, b"TT".to_vec(), 0),
+        ];
+
+        let streamed = ends(path);
+        std::fs::write(&fai, "chr1\t15\t6\t10\t11\nchr2\t2\t29\t2\t3\n").unwrap();
+        let fetched = ends(path);
+
+        assert_eq!(streamed, expected);
+        assert_eq!(fetched, expected);
+        assert_eq!(EndLength::Proportion(0.01).of(1001), 11);
+    }
+
+    #[test]
+    fn end_lengths() {
+        assert_eq!(EndLength::parse("10000").unwrap(), EndLength::Bases(10000));
+        assert_eq!(
+            EndLength::parse("0.05").unwrap(),
+            EndLength::Proportion(0.05)
+        );
+        assert!(EndLength::parse("0").is_err());
+        assert!(EndLength::parse("0.6").is_err());
+        assert!(EndLength::parse("10kb").is_err());
+
+        // ends that overlap are scanned as the whole record
+        let record = fasta::Record::with_attrs("chr1", None, b"AAAACCGGGG");
+        let merged = RegionRecord::merge_ends(RegionRecord::ends(&record, EndLength::Bases(6)));
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged[0].record.seq(), record.seq());
+        let apart = RegionRecord::merge_ends(RegionRecord::ends(&record, EndLength::Bases(4)));
+        assert_eq!(apart.len(), 2);
+        assert_eq!(apart[1].offset, 6);
+    }
+}
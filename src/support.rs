@@ -0,0 +1,307 @@
+use crate::tracts::{self, End, Tract};
+use crate::{clades, reader, utils, windows, SubCommand};
+use anyhow::{Context, Result};
+use rust_htslib::bam::{self, record::Cigar, Read};
+use std::fs::File;
+use std::io::{LineWriter, Write};
+use std::path::PathBuf;
+
+/// The header of the `tidk support` output.
+pub const SUPPORT_HEADER: &str = "id\tend\treads\tclipped_reads\ttelomeric_reads\ttract_length_min\ttract_length_median\ttract_length_max";
+
+/// The header of the optional per read tract file.
+pub const READ_TRACTS_HEADER: &str = "id\tend\tread\ttract_length\tstrand\ttelomeric_repeat";
+
+/// The reads aligned to one end of a record, and the telomeric
+/// tracts in the parts of them that hang over the end.
+#[derive(Debug, Clone)]
+pub struct EndSupport {
+    /// The record.
+    pub id: String,
+    /// Which end of the record.
+    pub end: End,
+    /// The number of reads aligned to within the end window.
+    pub reads: usize,
+    /// The number of those reads with an overhang long enough
+    /// to hold a tract.
+    pub clipped_reads: usize,
+    /// The length of the longest tract in each read with one.
+    pub tract_lengths: Vec<usize>,
+}
+
+impl EndSupport {
+    fn new(id: &str, end: End) -> Self {
+        EndSupport {
+            id: id.to_string(),
+            end,
+            reads: 0,
+            clipped_reads: 0,
+            tract_lengths: Vec::new(),
+        }
+    }
+
+    /// Format as a row of the `tidk support` output. The tract lengths
+    /// are `NA` if no reads have a tract.
+    pub fn to_tsv(&self) -> String {
+        let mut lengths = self.tract_lengths.clone();
+        lengths.sort_unstable();
+        let summary = match (lengths.first(), lengths.last()) {
+            (Some(min), Some(max)) => {
+                format!("{}\t{}\t{}", min, median(&lengths), max)
+            }
+            _ => "NA\tNA\tNA".into(),
+        };
+        format!(
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            self.id,
+            self.end,
+            self.reads,
+            self.clipped_reads,
+            self.tract_lengths.len(),
+            summary
+        )
+    }
+}
+
+/// The median of some sorted lengths, rounded down.
+fn median(sorted: &[usize]) -> usize {
+    // the two middle values are the same one if there's an odd number
+    let n = sorted.len();
+    (sorted[(n - 1) / 2] + sorted[n / 2]) / 2
+}
+
+/// The number of bases of a read that hang over an end of a record of
+/// `record_length` bases, or `None` if the read is not aligned to within
+/// `window` bases of that end. `aligned` is the 0-based start and exclusive
+/// end of the alignment on the record, and `clips` are the soft clipped
+/// bases at the start and end of the read.
+pub fn overhang(
+    end: End,
+    aligned: (usize, usize),
+    clips: (usize, usize),
+    record_length: usize,
+    window: usize,
+) -> Option<usize> {
+    match end {
+        End::Five if aligned.0 <= window => Some(clips.0),
+        End::Three if aligned.1 + window >= record_length => Some(clips.1),
+        _ => None,
+    }
+}
+
+/// The soft clipped bases at the start and end of a read. Hard clips
+/// are outside the soft clips in a cigar (e.g. `5H100S...`), so they are
+/// skipped first; the hard clipped bases aren't in the read, so can't
+/// be searched.
+pub fn soft_clips(cigar: &[Cigar]) -> (usize, usize) {
+    fn clip<'a>(mut ops: impl Iterator<Item = &'a Cigar>) -> usize {
+        match ops.find(|op| !matches!(op, Cigar::HardClip(_))) {
+            Some(Cigar::SoftClip(length)) => *length as usize,
+            _ => 0,
+        }
+    }
+    (clip(cigar.iter()), clip(cigar.iter().rev()))
+}
+
+/// The longest telomeric tract in a stretch of read sequence. The
+/// `matcher` must be built for the `telomeric_repeats`.
+pub fn longest_tract(
+    sequence: &[u8],
+    matcher: &windows::TelomericMatcher,
+    telomeric_repeats: &[&str],
+    params: tracts::TractParams,
+) -> Result<Option<Tract>> {
+    let motifs = matcher.find(sequence)?;
+    let read_tracts = tracts::call_record_tracts(
+        "",
+        telomeric_repeats,
+        &motifs,
+        utils::CountMode::Overlapping,
+        params,
+    );
+    Ok(read_tracts.into_iter().max_by_key(|t| t.length()))
+}
+
+/// The entry point for `tidk support`.
+///
+/// For each end of each record in a BAM of reads aligned to an assembly,
+/// count the reads that carry a telomeric tract in the part that hangs
+/// over the end.
+pub fn support(matches: &clap::ArgMatches, sc: SubCommand) -> Result<()> {
+    let input_bam = matches.get_one::<PathBuf>("bam").expect("errored by clap");
+
+    let telomeric_repeats = clades::telomeric_repeats_from_matches(matches)?;
+    let telomeric_repeats: Vec<&str> = telomeric_repeats.iter().map(|s| s.as_str()).collect();
+    eprintln!(
+        "[+]\tLooking for read support of telomeres using telomeric repeat(s): {}",
+        telomeric_repeats.join(", ")
+    );
+    // built once, as every clipped read is searched
+    let matcher = windows::TelomericMatcher::new(&telomeric_repeats, utils::Tolerance::Exact);
+
+    let tract_params = tracts::TractParams {
+        max_gap: *matches
+            .get_one::<usize>("max-gap")
+            .expect("defaulted by clap"),
+        min_length: *matches
+            .get_one::<usize>("min-tract-length")
+            .expect("defaulted by clap"),
+    };
+    let window = *matches
+        .get_one::<usize>("end-window")
+        .expect("defaulted by clap");
+    let options = reader::AlignmentOptions::from_matches(matches);
+
+    let mut bam = bam::IndexedReader::from_path(input_bam).with_context(|| {
+        format!(
+            "Failed to read {}, which needs an index (e.g. from `samtools index`)",
+            input_bam.display()
+        )
+    })?;
+    bam.set_threads(reader::decompression_threads())?;
+    if let Some(reference) = &options.reference {
+        bam.set_reference(reference)?;
+    }
+    let header = bam.header().clone();
+
+    let mut read_tracts_file = match matches.get_one::<PathBuf>("read-tracts") {
+        Some(path) => {
+            let mut file = LineWriter::new(File::create(path)?);
+            writeln!(file, "{}", READ_TRACTS_HEADER)?;
+            Some(file)
+        }
+        None => None,
+    };
+
+    let mut supported_ends = 0;
+    let mut total_ends = 0;
+    let mut record = bam::Record::new();
+
+    println!("{}", SUPPORT_HEADER);
+    for tid in 0..header.target_count() {
+        let id = String::from_utf8_lossy(header.tid2name(tid)).to_string();
+        let record_length = header.target_len(tid).unwrap_or(0) as usize;
+
+        for end in [End::Five, End::Three] {
+            let region = match end {
+                End::Five => (0, window.min(record_length)),
+                End::Three => (record_length.saturating_sub(window), record_length),
+            };
+            bam.fetch((tid, region.0 as u64, region.1 as u64))?;
+
+            let mut end_support = EndSupport::new(&id, end);
+            while let Some(result) = bam.read(&mut record) {
+                result?;
+                if !options.keep(&record) || record.is_unmapped() {
+                    continue;
+                }
+                let cigar = record.cigar();
+                let aligned = (record.pos() as usize, cigar.end_pos() as usize);
+                let clips = soft_clips(&cigar);
+                let Some(clip) = overhang(end, aligned, clips, record_length, window) else {
+                    continue;
+                };
+                end_support.reads += 1;
+                if clip < tract_params.min_length {
+                    continue;
+                }
+                end_support.clipped_reads += 1;
+
+                let sequence = record.seq().as_bytes();
+                let overhanging = match end {
+                    End::Five => &sequence[..clip],
+                    End::Three => &sequence[sequence.len() - clip..],
+                };
+                if let Some(tract) =
+                    longest_tract(overhanging, &matcher, &telomeric_repeats, tract_params)?
+                {
+                    if let Some(f) = read_tracts_file.as_mut() {
+                        writeln!(
+                            f,
+                            "{}\t{}\t{}\t{}\t{}\t{}",
+                            id,
+                            end,
+                            String::from_utf8_lossy(record.qname()),
+                            tract.length(),
+                            tract.strand,
+                            tract.telomeric_repeat
+                        )?;
+                    }
+                    end_support.tract_lengths.push(tract.length());
+                }
+            }
+
+            total_ends += 1;
+            if !end_support.tract_lengths.is_empty() {
+                supported_ends += 1;
+            }
+            println!("{}", end_support.to_tsv());
+        }
+    }
+    eprintln!(
+        "[+]\t{} of {} record ends have reads with telomeric tracts",
+        supported_ends, total_ends
+    );
+
+    // optional log file
+    sc.log(matches)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overhanging() {
+        // aligned from 10 to 500 of a 1000bp record, with clips of 50 and 20
+        let read = ((10, 500), (50, 20));
+        assert_eq!(overhang(End::Five, read.0, read.1, 1000, 100), Some(50));
+        assert_eq!(overhang(End::Three, read.0, read.1, 1000, 100), None);
+        assert_eq!(overhang(End::Three, read.0, read.1, 1000, 500), Some(20));
+        assert_eq!(overhang(End::Five, (200, 500), read.1, 1000, 100), None);
+    }
+
+    #[test]
+    fn clips() {
+        use Cigar::*;
+        assert_eq!(
+            soft_clips(&[SoftClip(50), Match(100), SoftClip(20)]),
+            (50, 20)
+        );
+        assert_eq!(
+            soft_clips(&[HardClip(5), SoftClip(100), Match(100), HardClip(7)]),
+            (100, 0)
+        );
+        assert_eq!(
+            soft_clips(&[Match(100), SoftClip(20), HardClip(3)]),
+            (0, 20)
+        );
+    }
+
+    #[test]
+    fn read_tract() {
+        let params = tracts::TractParams {
+            max_gap: 5,
+            min_length: 20,
+        };
+        let overhanging = format!("{}ACGTACGTAC{}", "CCTAA".repeat(3), "CCTAA".repeat(6));
+        let matcher = windows::TelomericMatcher::new(&["TTAGG"], utils::Tolerance::Exact);
+        let tract = longest_tract(overhanging.as_bytes(), &matcher, &["TTAGG"], params)
+            .unwrap()
+            .unwrap();
+        assert_eq!((tract.start, tract.length()), (25, 30));
+        assert_eq!(tract.strand, tracts::Strand::Reverse);
+    }
+
+    #[test]
+    fn summary() {
+        let mut end_support = EndSupport::new("chr1", End::Three);
+        assert_eq!(end_support.to_tsv(), "chr1\t3'\t0\t0\t0\tNA\tNA\tNA");
+        end_support.reads = 12;
+        end_support.clipped_reads = 5;
+        end_support.tract_lengths = vec![300, 100, 250, 400];
+        assert_eq!(end_support.to_tsv(), "chr1\t3'\t12\t5\t4\t100\t275\t400");
+    }
+}
@@ -0,0 +1,581 @@
+use crate::utils::{is_g_rich, CountMode, Motifs};
+use crate::windows::WindowCounts;
+use std::fmt::{self, Display};
+
+/// The strand a telomeric repeat was found on. Forward
+/// matches are of the telomeric repeat as queried, reverse
+/// matches are of its reverse complement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strand {
+    Forward,
+    Reverse,
+}
+
+impl Display for Strand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Strand::Forward => write!(f, "+"),
+            Strand::Reverse => write!(f, "-"),
+        }
+    }
+}
+
+/// How matches are merged into tracts.
+#[derive(Debug, Clone, Copy)]
+pub struct TractParams {
+    /// The largest number of bases between two matches
+    /// for them to be in the same tract.
+    pub max_gap: usize,
+    /// Tracts shorter than this (in bases) are not reported.
+    pub min_length: usize,
+}
+
+/// A contiguous telomeric tract, made up of matches of a
+/// telomeric repeat separated by no more than the maximum gap.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tract {
+    /// The record the tract is on.
+    pub id: String,
+    /// 0-based start of the first match.
+    pub start: usize,
+    /// 0-based, exclusive end of the last match.
+    pub end: usize,
+    /// Which strand the telomeric repeat is on.
+    pub strand: Strand,
+    /// The telomeric repeat, as queried.
+    pub telomeric_repeat: String,
+    /// The number of repeat units in the tract,
+    /// counted as in the window counts.
+    pub units: usize,
+    /// The proportion of bases in the tract covered
+    /// by a match.
+    pub purity: f64,
+}
+
+impl Tract {
+    /// The length of the tract in bases.
+    pub fn length(&self) -> usize {
+        self.end - self.start
+    }
+
+    /// The number of bases between the tract and the nearer
+    /// end of a record of `record_length` bases.
+    pub fn distance_from_ends(&self, record_length: usize) -> usize {
+        self.start.min(record_length.saturating_sub(self.end))
+    }
+
+    /// Format the tract as a BED6 line, with the number of units and
+    /// the purity as two extra columns. The score is the purity scaled
+    /// to 0-1000.
+    pub fn to_bed(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{:.3}",
+            self.id,
+            self.start,
+            self.end,
+            self.telomeric_repeat,
+            (self.purity * 1000.0).round() as usize,
+            self.strand,
+            self.units,
+            self.purity
+        )
+    }
+}
+
+/// Merge the matches of a telomeric repeat on one strand of a record into
+/// tracts. `indexes` are the sorted start positions of all the matches,
+/// including overlapping ones, and `count_mode` decides how many repeat
+/// units are counted in each tract.
+pub fn call_tracts(
+    id: &str,
+    telomeric_repeat: &str,
+    strand: Strand,
+    indexes: &[usize],
+    repeat_length: usize,
+    count_mode: CountMode,
+    params: TractParams,
+) -> Vec<Tract> {
+    let mut tracts = Vec::new();
+    let mut matches = indexes.iter().peekable();
+
+    while let Some(&first) = matches.next() {
+        let start = first;
+        let mut end = first + repeat_length;
+        let mut covered = repeat_length;
+        let mut tract_indexes = vec![first];
+
+        while let Some(&&next) = matches.peek() {
+            if next > end + params.max_gap {
+                break;
+            }
+            let next_end = next + repeat_length;
+            // only count the bases not already covered by a match
+            covered += next_end.saturating_sub(end.max(next));
+            end = end.max(next_end);
+            tract_indexes.push(next);
+            matches.next();
+        }
+
+        if end - start < params.min_length {
+            continue;
+        }
+
+        let units = count_mode
+            .apply(
+                Motifs {
+                    length: tract_indexes.len(),
+                    indexes: tract_indexes,
+                },
+                repeat_length,
+            )
+            .len();
+
+        tracts.push(Tract {
+            id: id.to_string(),
+            start,
+            end,
+            strand,
+            telomeric_repeat: telomeric_repeat.to_string(),
+            units,
+            purity: covered as f64 / (end - start) as f64,
+        });
+    }
+    tracts
+}
+
+/// Call the tracts of several telomeric repeats, on both strands of a
+/// record. `matches` are the forward and reverse strand matches of each
+/// telomeric repeat. The tracts are returned sorted by start.
+pub fn call_record_tracts(
+    id: &str,
+    telomeric_repeats: &[&str],
+    matches: &[(Motifs, Motifs)],
+    count_mode: CountMode,
+    params: TractParams,
+) -> Vec<Tract> {
+    let mut tracts = Vec::new();
+    for (telomeric_repeat, (forward, reverse)) in telomeric_repeats.iter().zip(matches) {
+        for (motifs, strand) in [(forward, Strand::Forward), (reverse, Strand::Reverse)] {
+            tracts.extend(call_tracts(
+                id,
+                telomeric_repeat,
+                strand,
+                &motifs.indexes,
+                telomeric_repeat.len(),
+                count_mode,
+                params,
+            ));
+        }
+    }
+    tracts.sort_by_key(|t| t.start);
+    tracts
+}
+
+/// The header of the interstitial telomeric sequence BED file.
+pub const INTERSTITIAL_HEADER: &str =
+    "#id\tstart\tend\ttelomeric_repeat\tscore\tstrand\tunits\tpurity\tlength\tdistance_from_end";
+
+/// Find the interstitial telomeric sequences (ITS) of a record of
+/// `record_length` bases: the tracts that are more than `min_distance`
+/// bases from both ends. Each comes with its distance from the nearer end.
+pub fn interstitial_tracts(
+    tracts: &[Tract],
+    record_length: usize,
+    min_distance: usize,
+) -> Vec<(&Tract, usize)> {
+    tracts
+        .iter()
+        .map(|t| (t, t.distance_from_ends(record_length)))
+        .filter(|(_, distance)| *distance > min_distance)
+        .collect()
+}
+
+/// One of the two ends of a record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum End {
+    /// The start of the record.
+    Five,
+    /// The end of the record.
+    Three,
+}
+
+impl Display for End {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            End::Five => write!(f, "5'"),
+            End::Three => write!(f, "3'"),
+        }
+    }
+}
+
+impl End {
+    /// The strand a telomeric repeat should be found on at this end of a
+    /// correctly oriented record. Reading along the record, the 5' end
+    /// carries the C-rich strand of the telomere, and the 3' end carries
+    /// the G-rich strand.
+    pub fn expected_strand(&self, telomeric_repeat: &str) -> Strand {
+        match (self, is_g_rich(telomeric_repeat)) {
+            (End::Five, true) | (End::Three, false) => Strand::Reverse,
+            (End::Five, false) | (End::Three, true) => Strand::Forward,
+        }
+    }
+
+    /// The end of a record of `record_length` bases that the
+    /// window from `start` to `end` is nearer.
+    pub fn nearer(start: usize, end: usize, record_length: usize) -> Self {
+        if start + end < record_length {
+            End::Five
+        } else {
+            End::Three
+        }
+    }
+}
+
+/// The header of the per record end summary.
+pub const TELOMERE_ENDS_HEADER: &str = "id\tend\ttract_length\trepeat_number\tdistance_from_end\tstrand\texpected_strand_repeat_number\tother_strand_repeat_number\torientation";
+
+/// The number of repeat units on each strand in the windows at one
+/// end of a record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EndCounts {
+    /// Units on the strand expected at this end of a correctly
+    /// oriented record.
+    pub expected: usize,
+    /// Units on the other strand.
+    pub other: usize,
+    /// The fewest units in the windows to check the orientation.
+    pub min_repeats: usize,
+}
+
+impl EndCounts {
+    /// Is this end the right way round? `None` if there are too few
+    /// units, or as many on each strand.
+    pub fn is_correctly_oriented(&self) -> Option<bool> {
+        if self.expected + self.other < self.min_repeats || self.expected == self.other {
+            None
+        } else {
+            Some(self.expected > self.other)
+        }
+    }
+}
+
+/// The telomere (if any) at one end of a record.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TelomereEnd {
+    /// The record.
+    pub id: String,
+    /// Which end of the record.
+    pub end: End,
+    /// The tract closest to this end, if there is one
+    /// close enough.
+    pub tract: Option<Tract>,
+    /// The number of bases between the tract and the end
+    /// of the record.
+    pub distance: Option<usize>,
+    /// The units on each strand in the windows at this end.
+    pub counts: Option<EndCounts>,
+}
+
+impl TelomereEnd {
+    /// Is the telomere at this end on the strand expected for a correctly
+    /// oriented record? `None` if there is no telomere at this end.
+    pub fn is_correctly_oriented(&self) -> Option<bool> {
+        self.tract
+            .as_ref()
+            .map(|t| t.strand == self.end.expected_strand(&t.telomeric_repeat))
+    }
+
+    /// Add the units on each strand near this end, summed over the
+    /// telomeric repeats and over the windows within `max_end_distance`
+    /// of the end of a record of `record_length` bases. The window at
+    /// the very end is always used, however short it is. Overlapping
+    /// windows are skipped, so no unit is counted twice.
+    pub fn add_window_counts(
+        &mut self,
+        counts: &[WindowCounts],
+        record_length: usize,
+        max_end_distance: usize,
+        min_repeats: usize,
+    ) {
+        let mut end_counts = EndCounts {
+            expected: 0,
+            other: 0,
+            min_repeats,
+        };
+        for repeat_counts in counts {
+            let near_end = |&(start, end): &(usize, usize)| match self.end {
+                End::Five => start <= max_end_distance,
+                End::Three => end + max_end_distance >= record_length,
+            };
+            // from the end of the record inwards
+            let mut windows: Vec<usize> = (0..repeat_counts.windows.len()).collect();
+            if self.end == End::Three {
+                windows.reverse();
+            }
+            // the bases of the record already counted, from the end
+            let mut counted: Option<(usize, usize)> = None;
+            for i in windows {
+                let window = repeat_counts.windows[i];
+                if !near_end(&window) {
+                    break;
+                }
+                let overlaps = counted.is_some_and(|(start, end)| match self.end {
+                    End::Five => window.0 < end,
+                    End::Three => window.1 > start,
+                });
+                if overlaps {
+                    continue;
+                }
+                counted = Some(match counted {
+                    Some((start, end)) => (start.min(window.0), end.max(window.1)),
+                    None => window,
+                });
+                let (Some(&forward), Some(&reverse)) =
+                    (repeat_counts.forward.get(i), repeat_counts.reverse.get(i))
+                else {
+                    continue;
+                };
+                let (expected, other) =
+                    match self.end.expected_strand(&repeat_counts.telomeric_repeat) {
+                        Strand::Forward => (forward, reverse),
+                        Strand::Reverse => (reverse, forward),
+                    };
+                end_counts.expected += expected;
+                end_counts.other += other;
+            }
+        }
+        self.counts = Some(end_counts);
+    }
+
+    /// The orientation of this end from the window counts, as `ok`,
+    /// `inverted` or `NA`.
+    pub fn orientation(&self) -> &'static str {
+        match self.counts.and_then(|c| c.is_correctly_oriented()) {
+            Some(true) => "ok",
+            Some(false) => "inverted",
+            None => "NA",
+        }
+    }
+
+    /// Format as a row of the end summary TSV.
+    pub fn to_tsv(&self) -> String {
+        let counts = match self.counts {
+            Some(c) => format!("{}\t{}\t{}", c.expected, c.other, self.orientation()),
+            None => "NA\tNA\tNA".into(),
+        };
+        let tract = match (&self.tract, self.distance) {
+            (Some(tract), Some(distance)) => format!(
+                "{}\t{}\t{}\t{}\t{}\t{}",
+                self.id,
+                self.end,
+                tract.length(),
+                tract.units,
+                distance,
+                tract.strand
+            ),
+            _ => format!("{}\t{}\t0\t0\tNA\t.", self.id, self.end),
+        };
+        format!("{}\t{}", tract, counts)
+    }
+}
+
+/// Find the telomere at each end of a record of `record_length` bases,
+/// which is the tract closest to that end, as long as it is no more than
+/// `max_distance` bases away from it. A tract can only be the telomere of
+/// the end it is nearer to.
+pub fn telomere_ends(
+    id: &str,
+    record_length: usize,
+    tracts: &[Tract],
+    max_distance: usize,
+) -> [TelomereEnd; 2] {
+    let distances = |t: &Tract| (t.start, record_length.saturating_sub(t.end));
+    let five = tracts
+        .iter()
+        .filter(|t| distances(t).0 <= distances(t).1)
+        .map(|t| (distances(t).0, t))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance);
+    let three = tracts
+        .iter()
+        .filter(|t| distances(t).1 < distances(t).0)
+        .map(|t| (distances(t).1, t))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance);
+
+    [(End::Five, five), (End::Three, three)].map(|(end, closest)| TelomereEnd {
+        id: id.to_string(),
+        end,
+        tract: closest.map(|(_, t)| t.clone()),
+        distance: closest.map(|(d, _)| d),
+        counts: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::find_motifs;
+
+    const PARAMS: TractParams = TractParams {
+        max_gap: 5,
+        min_length: 10,
+    };
+
+    fn tracts_for(sequence: &str, params: TractParams) -> Vec<Tract> {
+        let motifs = find_motifs("TTAGG", sequence.as_bytes());
+        call_tracts(
+            "test",
+            "TTAGG",
+            Strand::Forward,
+            &motifs.indexes,
+            5,
+            CountMode::Overlapping,
+            params,
+        )
+    }
+
+    #[test]
+    fn merge_adjacent() {
+        // three units, a 3bp gap, two units, then far away a single unit
+        let tracts = tracts_for(
+            "TTAGGTTAGGTTAGGCCCTTAGGTTAGGCATCACACTGATCATCTGACATTAGG",
+            PARAMS,
+        );
+        assert_eq!(tracts.len(), 1);
+        assert_eq!(tracts[0].start, 0);
+        assert_eq!(tracts[0].end, 28);
+        assert_eq!(tracts[0].units, 5);
+        assert!((tracts[0].purity - 25.0 / 28.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn min_length() {
+        // the lone unit at the end is reported with no minimum length
+        let tracts = tracts_for(
+            "TTAGGTTAGGTTAGGCCCTTAGGTTAGGCATCACACTGATCATCTGACATTAGG",
+            TractParams {
+                max_gap: 5,
+                min_length: 0,
+            },
+        );
+        assert_eq!(tracts.len(), 2);
+        assert_eq!((tracts[1].start, tracts[1].end), (49, 54));
+    }
+
+    #[test]
+    fn bed_line() {
+        let tracts = tracts_for("TTAGGTTAGGTTAGG", PARAMS);
+        assert_eq!(tracts[0].to_bed(), "test\t0\t15\tTTAGG\t1000\t+\t3\t1.000");
+    }
+
+    #[test]
+    fn interstitial() {
+        let sequence = format!(
+            "{}TTAGGTTAGGTTAGG{}TTAGGTTAGGTTAGG",
+            "ACGT".repeat(10),
+            "ACGT".repeat(3)
+        );
+        let tracts = tracts_for(&sequence, PARAMS);
+        assert_eq!(tracts.len(), 2);
+
+        // the first tract is 40bp from the start, the second is at the end
+        let its = interstitial_tracts(&tracts, sequence.len(), 20);
+        assert_eq!(its.len(), 1);
+        assert_eq!(its[0].0.start, 40);
+        assert_eq!(its[0].1, 27);
+
+        assert!(interstitial_tracts(&tracts, sequence.len(), 40).is_empty());
+    }
+
+    #[test]
+    fn ends() {
+        // a short arm of CCTAA at the start, TTAGG at the end
+        let sequence = format!(
+            "CCCCTAACCTAACCTAACCTAA{}TTAGGTTAGGTTAGGTTAGGAAAA",
+            "ACGT".repeat(20)
+        );
+        let forward = find_motifs("TTAGG", sequence.as_bytes());
+        let reverse = find_motifs("CCTAA", sequence.as_bytes());
+        let mut tracts = Vec::new();
+        for (motifs, strand) in [(forward, Strand::Forward), (reverse, Strand::Reverse)] {
+            tracts.extend(call_tracts(
+                "test",
+                "TTAGG",
+                strand,
+                &motifs.indexes,
+                5,
+                CountMode::Overlapping,
+                PARAMS,
+            ));
+        }
+
+        let [five, three] = telomere_ends("test", sequence.len(), &tracts, 10);
+        assert_eq!(five.to_tsv(), "test\t5'\t20\t4\t2\t-\tNA\tNA\tNA");
+        assert_eq!(three.to_tsv(), "test\t3'\t20\t4\t4\t+\tNA\tNA\tNA");
+        // TTAGG is G-rich, so this is the right way round
+        assert_eq!(five.is_correctly_oriented(), Some(true));
+        assert_eq!(three.is_correctly_oriented(), Some(true));
+
+        // too far from the ends
+        let [five, _] = telomere_ends("test", sequence.len(), &tracts, 1);
+        assert_eq!(five.to_tsv(), "test\t5'\t0\t0\tNA\t.\tNA\tNA\tNA");
+
+        // only the 3' tract, which can't be the telomere of the 5' end too
+        tracts.retain(|t| t.strand == Strand::Forward);
+        let [five, three] = telomere_ends("test", sequence.len(), &tracts, 1000);
+        assert_eq!(five.to_tsv(), "test\t5'\t0\t0\tNA\t.\tNA\tNA\tNA");
+        assert_eq!(three.distance, Some(4));
+    }
+
+    #[test]
+    fn end_orientation() {
+        let counts = [WindowCounts {
+            telomeric_repeat: "TTAGG".into(),
+            windows: vec![(0, 100), (100, 200), (200, 250)],
+            forward: vec![1, 0, 3],
+            reverse: vec![15, 0, 12],
+        }];
+        let [mut five, mut three] = telomere_ends("test", 250, &[], 10);
+        five.add_window_counts(&counts, 250, 10, 10);
+        three.add_window_counts(&counts, 250, 10, 10);
+        // the C-rich strand at the 5' end is right, but not at the 3' end
+        assert_eq!(five.orientation(), "ok");
+        assert_eq!(three.orientation(), "inverted");
+        assert_eq!(three.to_tsv(), "test\t3'\t0\t0\tNA\t.\t3\t12\tinverted");
+
+        // too few units to tell
+        five.add_window_counts(&counts, 250, 10, 20);
+        assert_eq!(five.orientation(), "NA");
+    }
+
+    #[test]
+    fn end_orientation_short_window() {
+        // the last window of a 201 base record is a single base
+        let counts = [WindowCounts {
+            telomeric_repeat: "TTAGG".into(),
+            windows: vec![(0, 100), (100, 200), (200, 201)],
+            forward: vec![0, 12, 0],
+            reverse: vec![0, 1, 0],
+        }];
+        let [_, mut three] = telomere_ends("test", 201, &[], 10);
+        three.add_window_counts(&counts, 201, 0, 10);
+        assert_eq!(three.orientation(), "NA");
+        // the windows within the distance of the end are summed
+        three.add_window_counts(&counts, 201, 50, 10);
+        assert_eq!(three.orientation(), "ok");
+        assert_eq!(three.counts.map(|c| (c.expected, c.other)), Some((12, 1)));
+
+        // overlapping windows are only counted once
+        let counts = [WindowCounts {
+            telomeric_repeat: "TTAGG".into(),
+            windows: vec![(0, 100), (50, 150), (100, 200), (150, 201)],
+            forward: vec![0, 6, 12, 6],
+            reverse: vec![0, 0, 1, 1],
+        }];
+        three.add_window_counts(&counts, 201, 40, 10);
+        assert_eq!(three.counts.map(|c| (c.expected, c.other)), Some((6, 1)));
+        three.add_window_counts(&counts, 201, 120, 10);
+        assert_eq!(three.counts.map(|c| (c.expected, c.other)), Some((12, 1)));
+    }
+}
@@ -0,0 +1,366 @@
+use anyhow::{bail, Context, Result};
+use bio::io::fasta;
+use std::collections::HashMap;
+use std::fs::File;
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+
+/// The signature at the start of a 2bit file, in the byte order
+/// of the machine that wrote it.
+const SIGNATURE: u32 = 0x1A41_2743;
+
+/// The bases packed into each byte, from the high bits down.
+const BASES: [u8; 4] = [b'T', b'C', b'A', b'G'];
+
+/// Does a file starting with these bytes look like 2bit?
+pub fn is_twobit(bytes: &[u8]) -> bool {
+    match bytes {
+        [a, b, c, d, ..] => {
+            let signature = [*a, *b, *c, *d];
+            u32::from_le_bytes(signature) == SIGNATURE || u32::from_be_bytes(signature) == SIGNATURE
+        }
+        _ => false,
+    }
+}
+
+/// A UCSC 2bit genome. The index at the start of the file says where
+/// each record is, so any part of a record can be read without
+/// reading the rest of the file.
+pub struct TwoBit {
+    path: PathBuf,
+    file: File,
+    big_endian: bool,
+    /// The names of the records, in the order they are in the file.
+    ids: Vec<String>,
+    /// Where the header of each record starts.
+    offsets: HashMap<String, u64>,
+}
+
+/// The layout of a record, from the header before its bases.
+struct RecordHeader {
+    length: usize,
+    /// The runs of N, as starts and lengths.
+    n_blocks: Vec<(usize, usize)>,
+    /// The soft-masked (lower case) runs, as starts and lengths.
+    mask_blocks: Vec<(usize, usize)>,
+    /// Where the packed bases start.
+    dna_offset: u64,
+}
+
+impl TwoBit {
+    /// Open the 2bit file at `path` and read its index.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file =
+            File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        let mut twobit = TwoBit {
+            path: path.to_owned(),
+            file,
+            big_endian: false,
+            ids: Vec::new(),
+            offsets: HashMap::new(),
+        };
+
+        let header = twobit.read_at(0, 16)?;
+        if !is_twobit(&header) {
+            bail!("{} is not a 2bit file", path.display())
+        }
+        twobit.big_endian =
+            u32::from_be_bytes([header[0], header[1], header[2], header[3]]) == SIGNATURE;
+        let header = twobit.u32s(&header);
+        // version 1 has 64 bit offsets, for files over 4GB
+        let wide = match header[1] {
+            0 => false,
+            1 => true,
+            version => bail!(
+                "{} is version {} of 2bit, which can't be read",
+                path.display(),
+                version
+            ),
+        };
+
+        let mut position = 16;
+        for _ in 0..header[2] {
+            let name_length = twobit.read_at(position, 1)?[0] as usize;
+            position += 1;
+            let id = String::from_utf8_lossy(&twobit.read_at(position, name_length)?).to_string();
+            position += name_length as u64;
+            let offset = if wide {
+                let bytes: [u8; 8] = twobit.read_at(position, 8)?.try_into().expect("8 bytes");
+                position += 8;
+                if twobit.big_endian {
+                    u64::from_be_bytes(bytes)
+                } else {
+                    u64::from_le_bytes(bytes)
+                }
+            } else {
+                position += 4;
+                twobit.read_u32(position - 4)? as u64
+            };
+            if twobit.offsets.insert(id.clone(), offset).is_some() {
+                bail!("Record {} is in {} twice", id, path.display())
+            }
+            twobit.ids.push(id);
+        }
+        Ok(twobit)
+    }
+
+    /// The names of the records, in the order they are in the file.
+    pub fn ids(&self) -> &[String] {
+        &self.ids
+    }
+
+    /// The length of record `id`, or `None` if it's not in the file.
+    pub fn record_length(&self, id: &str) -> Result<Option<usize>> {
+        match self.offsets.get(id) {
+            Some(offset) => Ok(Some(self.read_u32(*offset)? as usize)),
+            None => Ok(None),
+        }
+    }
+
+    /// Read the bases from `start` up to `end` of record `id`, with
+    /// runs of N and soft-masked (lower case) runs as they were in the
+    /// fasta the file was made from.
+    pub fn fetch(&self, id: &str, start: usize, end: usize) -> Result<Vec<u8>> {
+        let offset = *self
+            .offsets
+            .get(id)
+            .with_context(|| format!("There is no record {} in {}", id, self.path.display()))?;
+        let header = self.record_header(offset)?;
+        let end = end.min(header.length);
+        if start >= end {
+            return Ok(Vec::new());
+        }
+
+        // only the bytes holding the bases asked for
+        let first_byte = start / 4;
+        let packed = self.read_at(
+            header.dna_offset + first_byte as u64,
+            end.div_ceil(4) - first_byte,
+        )?;
+        let mut seq: Vec<u8> = (start..end)
+            .map(|i| {
+                let byte = packed[i / 4 - first_byte];
+                BASES[(byte >> (6 - 2 * (i % 4))) as usize & 3]
+            })
+            .collect();
+
+        // the runs overlapping the range
+        let overlap = |(block_start, block_length): (usize, usize)| {
+            let from = block_start.max(start);
+            let to = (block_start + block_length).min(end);
+            if from < to {
+                Some((from - start)..(to - start))
+            } else {
+                None
+            }
+        };
+        for range in header.n_blocks.into_iter().filter_map(overlap) {
+            seq[range].fill(b'N');
+        }
+        for range in header.mask_blocks.into_iter().filter_map(overlap) {
+            seq[range].make_ascii_lowercase();
+        }
+        Ok(seq)
+    }
+
+    /// Read every record, whole, in the order they are in the file.
+    pub fn into_records(self) -> impl Iterator<Item = Result<fasta::Record>> + Send {
+        let ids = self.ids.clone();
+        ids.into_iter().map(move |id| {
+            let seq = self.fetch(&id, 0, usize::MAX)?;
+            Ok(fasta::Record::with_attrs(&id, None, &seq))
+        })
+    }
+
+    /// Read the header of the record starting at `offset`.
+    fn record_header(&self, offset: u64) -> Result<RecordHeader> {
+        let length = self.read_u32(offset)? as usize;
+        let mut position = offset + 4;
+        // a count, then that many starts, then that many lengths
+        let mut blocks = || -> Result<Vec<(usize, usize)>> {
+            let count = self.read_u32(position)? as usize;
+            let values = self.u32s(&self.read_at(position + 4, 8 * count)?);
+            position += 4 + 8 * count as u64;
+            let (starts, lengths) = values.split_at(count);
+            Ok(starts
+                .iter()
+                .zip(lengths)
+                .map(|(start, length)| (*start as usize, *length as usize))
+                .collect())
+        };
+        let n_blocks = blocks()?;
+        let mask_blocks = blocks()?;
+        Ok(RecordHeader {
+            length,
+            n_blocks,
+            mask_blocks,
+            // past the reserved word
+            dna_offset: position + 4,
+        })
+    }
+
+    fn read_at(&self, offset: u64, length: usize) -> Result<Vec<u8>> {
+        let mut bytes = vec![0; length];
+        self.file
+            .read_exact_at(&mut bytes, offset)
+            .with_context(|| format!("Failed to read {}, is it truncated?", self.path.display()))?;
+        Ok(bytes)
+    }
+
+    fn read_u32(&self, offset: u64) -> Result<u32> {
+        Ok(self.u32s(&self.read_at(offset, 4)?)[0])
+    }
+
+    /// Words in the byte order of the file.
+    fn u32s(&self, bytes: &[u8]) -> Vec<u32> {
+        bytes
+            .chunks_exact(4)
+            .map(|word| {
+                let word = [word[0], word[1], word[2], word[3]];
+                if self.big_endian {
+                    u32::from_be_bytes(word)
+                } else {
+                    u32::from_le_bytes(word)
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::{self, AlignmentOptions, EndLength, Region};
+    use crate::utils::TempFile;
+
+    /// Pack fasta records into a (little endian, version 0) 2bit file.
+    fn encode(records: &[(&str, &[u8])]) -> Vec<u8> {
+        let runs = |seq: &[u8], in_run: &dyn Fn(u8) -> bool| {
+            let mut runs: Vec<(u32, u32)> = Vec::new();
+            for (i, base) in seq.iter().enumerate() {
+                if in_run(*base) {
+                    match runs.last_mut() {
+                        Some((start, length)) if (*start + *length) as usize == i => *length += 1,
+                        _ => runs.push((i as u32, 1)),
+                    }
+                }
+            }
+            runs
+        };
+        let words = |out: &mut Vec<u8>, runs: &[(u32, u32)]| {
+            out.extend((runs.len() as u32).to_le_bytes());
+            for (start, _) in runs {
+                out.extend(start.to_le_bytes());
+            }
+            for (_, length) in runs {
+                out.extend(length.to_le_bytes());
+            }
+        };
+
+        let index_length: usize = records.iter().map(|(id, _)| 5 + id.len()).sum();
+        let mut bodies = Vec::new();
+        let mut index = Vec::new();
+        for (id, seq) in records {
+            index.push(id.len() as u8);
+            index.extend(id.as_bytes());
+            index.extend(((16 + index_length + bodies.len()) as u32).to_le_bytes());
+
+            bodies.extend((seq.len() as u32).to_le_bytes());
+            words(&mut bodies, &runs(seq, &|b| b.eq_ignore_ascii_case(&b'N')));
+            words(&mut bodies, &runs(seq, &|b| b.is_ascii_lowercase()));
+            bodies.extend(0u32.to_le_bytes());
+            for chunk in seq.chunks(4) {
+                let mut byte = 0;
+                for (i, base) in chunk.iter().enumerate() {
+                    let code = match base.to_ascii_uppercase() {
+                        b'C' => 1,
+                        b'A' => 2,
+                        b'G' => 3,
+                        _ => 0,
+                    };
+                    byte |= code << (6 - 2 * i);
+                }
+                bodies.push(byte);
+            }
+        }
+
+        let mut out = Vec::new();
+        for word in [SIGNATURE, 0, records.len() as u32, 0] {
+            out.extend(word.to_le_bytes());
+        }
+        out.extend(index);
+        out.extend(bodies);
+        out
+    }
+
+    #[test]
+    fn fetch() {
+        let file = TempFile::new("twobit.2bit");
+        let path = &file.path;
+        std::fs::write(
+            path,
+            encode(&[("chr1", b"ccctaaNNNNAACCCTAAttaggg"), ("chr2", b"GATTACA")]),
+        )
+        .unwrap();
+        let twobit = TwoBit::open(path).unwrap();
+
+        assert_eq!(twobit.ids(), ["chr1", "chr2"]);
+        assert_eq!(twobit.record_length("chr1").unwrap(), Some(24));
+        assert_eq!(twobit.record_length("chr3").unwrap(), None);
+        assert_eq!(
+            twobit.fetch("chr1", 0, 24).unwrap(),
+            b"ccctaaNNNNAACCCTAAttaggg"
+        );
+        // not on a byte boundary, and across the N and masked runs
+        assert_eq!(twobit.fetch("chr1", 5, 11).unwrap(), b"aNNNNA");
+        assert_eq!(twobit.fetch("chr1", 17, 30).unwrap(), b"Attaggg");
+        assert_eq!(twobit.fetch("chr2", 3, 3).unwrap(), b"");
+        assert!(twobit.fetch("chr3", 0, 1).is_err());
+
+        let records: Vec<_> = twobit.into_records().map(|r| r.unwrap()).collect();
+        assert_eq!(records[1].id(), "chr2");
+        assert_eq!(records[1].seq(), b"GATTACA");
+    }
+
+    #[test]
+    fn read_through_reader() {
+        let file = TempFile::new("reader.2bit");
+        let path = &file.path;
+        std::fs::write(
+            path,
+            encode(&[("chr1", b"AAAAACCCCCGGGGG"), ("chr2", b"TT")]),
+        )
+        .unwrap();
+        let options = AlignmentOptions::default();
+
+        let records: Vec<_> = reader::records(path, &options)
+            .unwrap()
+            .map(|r| r.unwrap().seq().to_vec())
+            .collect();
+        assert_eq!(records, vec![b"AAAAACCCCCGGGGG".to_vec(), b"TT".to_vec()]);
+
+        let regions = vec![Region::parse("chr1:4-12").unwrap()];
+        let region = reader::region_records(path, &regions, &options)
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(region.record.seq(), b"AACCCCCGG");
+        assert_eq!((region.offset, region.record_length), (3, 15));
+
+        let ends: Vec<_> = reader::end_records(path, EndLength::Bases(4), &options)
+            .unwrap()
+            .map(|ends| {
+                let [five, three] = ends.unwrap();
+                (
+                    five.record.seq().to_vec(),
+                    three.record.seq().to_vec(),
+                    three.offset,
+                )
+            })
+            .collect();
+        assert_eq!(ends[0], (b"AAAA".to_vec(), b"GGGG".to_vec(), 11));
+        assert_eq!(ends[1], (b"TT".to_vec(), b"TT".to_vec(), 0));
+    }
+}
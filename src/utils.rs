@@ -2,29 +2,36 @@ use bio::pattern_matching::bom::BOM;
 // I'd like to use shift_and, but may have to wait until next public release
 // of rust-bio (or hard code it here...)
 // see https://github.com/rust-bio/rust-bio/blob/master/src/pattern_matching/shift_and.rs
+use aho_corasick::AhoCorasick;
+use anyhow::{bail, Result};
 use bio::pattern_matching::kmp::KMP;
+use bio::pattern_matching::myers::{Myers, MyersBuilder};
 use lexical_sort::{natural_lexical_cmp, StringSort};
 use std::cmp::min;
+use std::collections::HashMap;
 
 /// This does the hard lifting in `tidk search` and `tidk find`
 /// take input putative telomeric repeat (motif) and search against
 /// a dna sequence. Optimised for motif length.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Motifs {
     pub indexes: Vec<usize>,
     pub length: usize,
 }
 
-/// Find all the occurrences of a motif in a DNA string.
-pub fn find_motifs(motif: &str, string: &str) -> Motifs {
+/// Find all the occurrences of a motif in a DNA string. The motif
+/// may contain IUPAC ambiguity codes.
+pub fn find_motifs(motif: &str, string: &[u8]) -> Motifs {
     let motif_length = motif.len();
 
-    let matches = if motif_length < 65 {
+    let matches = if is_degenerate(motif) {
+        hamming_matches(motif.as_bytes(), string, 0)
+    } else if motif_length < 65 {
         let matcher = KMP::new(motif.as_bytes());
-        matcher.find_all(string.as_bytes()).collect::<Vec<usize>>()
+        matcher.find_all(string).collect::<Vec<usize>>()
     } else {
         let matcher = BOM::new(motif.as_bytes());
-        matcher.find_all(string.as_bytes()).collect::<Vec<usize>>()
+        matcher.find_all(string).collect::<Vec<usize>>()
     };
 
     Motifs {
@@ -33,6 +40,368 @@ pub fn find_motifs(motif: &str, string: &str) -> Motifs {
     }
 }
 
+/// The largest number of concrete sequences an ambiguous motif
+/// may expand to before [`MultiMotifs`] gives up on it.
+const MAX_MOTIF_VARIANTS: usize = 4096;
+
+/// Find the occurrences of several motifs in a single pass over
+/// a DNA string, using an Aho-Corasick automaton. Motifs containing
+/// IUPAC ambiguity codes are expanded into all the sequences they
+/// stand for.
+pub struct MultiMotifs {
+    automaton: AhoCorasick,
+    /// For each (unique) pattern in the automaton, the motifs
+    /// it belongs to.
+    owners: Vec<Vec<usize>>,
+    /// The number of motifs queried.
+    motif_number: usize,
+}
+
+impl MultiMotifs {
+    /// Build the automaton. Returns `None` if an ambiguous motif
+    /// expands to too many sequences to be worth it.
+    pub fn new(motifs: &[&str]) -> Option<Self> {
+        let mut patterns: Vec<String> = Vec::new();
+        let mut owners: Vec<Vec<usize>> = Vec::new();
+        let mut seen: HashMap<String, usize> = HashMap::new();
+
+        for (motif_index, motif) in motifs.iter().enumerate() {
+            for variant in expand_iupac(motif, MAX_MOTIF_VARIANTS)? {
+                // the same sequence may turn up more than once, e.g. for
+                // palindromic repeats, so only put it in the automaton once.
+                match seen.get(&variant) {
+                    Some(&pattern_index) => owners[pattern_index].push(motif_index),
+                    None => {
+                        seen.insert(variant.clone(), patterns.len());
+                        patterns.push(variant);
+                        owners.push(vec![motif_index]);
+                    }
+                }
+            }
+        }
+
+        Some(Self {
+            automaton: AhoCorasick::new(&patterns),
+            owners,
+            motif_number: motifs.len(),
+        })
+    }
+
+    /// Find all the (overlapping) occurrences of each motif in a DNA
+    /// string. The returned [`Motifs`] are in the same order as the
+    /// motifs passed to [`MultiMotifs::new`].
+    pub fn find_all(&self, string: &[u8]) -> Vec<Motifs> {
+        let mut indexes = vec![Vec::new(); self.motif_number];
+
+        for mat in self.automaton.find_overlapping_iter(string) {
+            for &motif_index in &self.owners[mat.pattern()] {
+                indexes[motif_index].push(mat.start());
+            }
+        }
+
+        indexes
+            .into_iter()
+            .map(|mut i| {
+                // all variants of a motif are the same length, so these are
+                // sorted already, unless the same motif was passed twice.
+                i.sort_unstable();
+                i.dedup();
+                Motifs {
+                    length: i.len(),
+                    indexes: i,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Expand a motif with IUPAC ambiguity codes into every concrete
+/// sequence it could match. Returns `None` if there would be more
+/// than `limit` of them.
+pub fn expand_iupac(motif: &str, limit: usize) -> Option<Vec<String>> {
+    let mut variants = vec![String::with_capacity(motif.len())];
+
+    for code in motif.bytes() {
+        let bases: Vec<char> = "ACGT"
+            .chars()
+            .filter(|b| iupac_mask(code) & base_mask(*b as u8) != 0)
+            .collect();
+        if bases.is_empty() || variants.len() * bases.len() > limit {
+            return None;
+        }
+        variants = variants
+            .iter()
+            .flat_map(|v| {
+                bases.iter().map(move |b| {
+                    let mut v = v.clone();
+                    v.push(*b);
+                    v
+                })
+            })
+            .collect();
+    }
+    Some(variants)
+}
+
+/// The start and end coordinates (0-based, half open) of windows of
+/// `window_size` bases, starting every `step` bases along a sequence
+/// of `length` bases. The last window is cut short at the end of the
+/// sequence. When `step` equals `window_size`, the windows tile the
+/// sequence without overlapping.
+pub fn window_coordinates(length: usize, window_size: usize, step: usize) -> Vec<(usize, usize)> {
+    let mut coordinates = Vec::new();
+    if window_size == 0 || step == 0 {
+        return coordinates;
+    }
+
+    let mut start = 0;
+    while start < length {
+        let end = (start + window_size).min(length);
+        coordinates.push((start, end));
+        if end == length {
+            break;
+        }
+        start += step;
+    }
+    coordinates
+}
+
+/// Count the matches that start in each window. `indexes` are the
+/// (sorted) start positions of matches found along the whole sequence,
+/// so a repeat unit crossing a window boundary is assigned to the
+/// window it starts in, rather than being missed altogether.
+pub fn count_in_windows(indexes: &[usize], windows: &[(usize, usize)]) -> Vec<usize> {
+    windows
+        .iter()
+        .map(|(start, end)| {
+            indexes.partition_point(|i| i < end) - indexes.partition_point(|i| i < start)
+        })
+        .collect()
+}
+
+/// How closely a stretch of sequence has to resemble the
+/// telomeric repeat in order to be counted as a match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tolerance {
+    /// Only exact matches are counted.
+    Exact,
+    /// Allow up to this many substitutions (Hamming distance).
+    Mismatches(u8),
+    /// Allow up to this many substitutions, insertions or
+    /// deletions (Levenshtein distance).
+    Edits(u8),
+}
+
+impl Tolerance {
+    /// Build a tolerance from the `--mismatches` and `--edits`
+    /// values on the command line. These are mutually exclusive.
+    pub fn new(mismatches: Option<u8>, edits: Option<u8>) -> Self {
+        match (mismatches, edits) {
+            (Some(m), _) if m > 0 => Tolerance::Mismatches(m),
+            (_, Some(e)) if e > 0 => Tolerance::Edits(e),
+            _ => Tolerance::Exact,
+        }
+    }
+}
+
+impl std::fmt::Display for Tolerance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Tolerance::Exact => write!(f, "exact matches only"),
+            Tolerance::Mismatches(m) => write!(f, "up to {} mismatches", m),
+            Tolerance::Edits(e) => write!(f, "up to {} edits", e),
+        }
+    }
+}
+
+/// Find all the occurrences of a motif in a DNA string, allowing
+/// for sequencing errors or variant repeat units depending on the
+/// [`Tolerance`] chosen.
+pub fn find_motifs_with_tolerance(
+    motif: &str,
+    string: &[u8],
+    tolerance: Tolerance,
+) -> Result<Motifs> {
+    let matches = match tolerance {
+        Tolerance::Exact => return Ok(find_motifs(motif, string)),
+        Tolerance::Mismatches(m) => hamming_matches(motif.as_bytes(), string, m),
+        Tolerance::Edits(e) => levenshtein_matches(motif, string, e)?,
+    };
+
+    Ok(Motifs {
+        length: matches.len(),
+        indexes: matches,
+    })
+}
+
+/// Start positions of every window of `text` within `max_mismatches`
+/// substitutions of `motif`. A base in the text matches if it is one
+/// of the bases the (possibly ambiguous) motif base stands for.
+fn hamming_matches(motif: &[u8], text: &[u8], max_mismatches: u8) -> Vec<usize> {
+    if motif.is_empty() || text.len() < motif.len() {
+        return vec![];
+    }
+    let max_mismatches = max_mismatches as usize;
+    let motif_masks: Vec<u8> = motif.iter().map(|b| iupac_mask(*b)).collect();
+
+    text.windows(motif.len())
+        .enumerate()
+        .filter(|(_, window)| {
+            let mut mismatches = 0;
+            for (a, b) in window.iter().zip(&motif_masks) {
+                if base_mask(*a) & b == 0 {
+                    mismatches += 1;
+                    if mismatches > max_mismatches {
+                        return false;
+                    }
+                }
+            }
+            true
+        })
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Start positions of matches within `max_edits` of `motif`, using
+/// Myers' bit-parallel algorithm. An approximate hit is reported at
+/// every one of a run of neighbouring end positions, so each run is
+/// collapsed to its best scoring match. In a tandem tract the runs of
+/// neighbouring units touch, so a run is cut every motif length, to
+/// keep one match per unit.
+fn levenshtein_matches(motif: &str, text: &[u8], max_edits: u8) -> Result<Vec<usize>> {
+    if motif.is_empty() {
+        return Ok(vec![]);
+    }
+    if motif.len() > 64 {
+        bail!(
+            "--edits supports repeats of at most 64 bases, and {} is {} bases long.",
+            motif,
+            motif.len()
+        )
+    }
+    let motif_bytes = motif.as_bytes();
+    let mut myers = if is_degenerate(motif) {
+        let mut builder = MyersBuilder::new();
+        for (code, bases) in IUPAC_CODES {
+            builder.ambig(*code, bases.as_bytes());
+        }
+        builder.build_64(motif_bytes)
+    } else {
+        Myers::<u64>::new(motif_bytes)
+    };
+
+    let mut starts = Vec::new();
+    // (start, distance) of the best match in the current run
+    let mut best: Option<(usize, u8)> = None;
+    // the end of the first match in the current run
+    let mut run_end = 0;
+    let mut previous_end = 0;
+
+    for (start, end, dist) in myers.find_all(text, max_edits) {
+        match best {
+            Some((_, best_dist))
+                if end == previous_end + 1 && end < run_end + motif_bytes.len() =>
+            {
+                if dist < best_dist {
+                    best = Some((start, dist));
+                }
+            }
+            _ => {
+                if let Some((s, _)) = best {
+                    starts.push(s);
+                }
+                best = Some((start, dist));
+                run_end = end;
+            }
+        }
+        previous_end = end;
+    }
+    if let Some((s, _)) = best {
+        starts.push(s);
+    }
+    // the best starts of neighbouring runs can go backwards, or be the
+    // same, and everything downstream expects sorted, unique starts
+    starts.sort_unstable();
+    starts.dedup();
+    Ok(starts)
+}
+
+/// The IUPAC nucleotide ambiguity codes, and the bases they stand for.
+const IUPAC_CODES: &[(u8, &str)] = &[
+    (b'R', "AG"),
+    (b'Y', "CT"),
+    (b'S', "CG"),
+    (b'W', "AT"),
+    (b'K', "GT"),
+    (b'M', "AC"),
+    (b'B', "CGT"),
+    (b'D', "AGT"),
+    (b'H', "ACT"),
+    (b'V', "ACG"),
+    (b'N', "ACGT"),
+];
+
+/// The bases a (possibly ambiguous) IUPAC code stands for, as a
+/// bitmask where A = 1, C = 2, G = 4 and T = 8. Unknown codes match
+/// nothing.
+fn iupac_mask(code: u8) -> u8 {
+    match code {
+        b'A' | b'C' | b'G' | b'T' => base_mask(code),
+        _ => IUPAC_CODES
+            .iter()
+            .find(|(c, _)| *c == code)
+            .map(|(_, bases)| bases.bytes().fold(0, |mask, b| mask | base_mask(b)))
+            .unwrap_or(0),
+    }
+}
+
+/// The bitmask for a single base in the sequence being searched.
+/// Anything other than A, C, G or T (e.g. N) never matches.
+fn base_mask(base: u8) -> u8 {
+    match base {
+        b'A' => 1,
+        b'C' => 2,
+        b'G' => 4,
+        b'T' => 8,
+        _ => 0,
+    }
+}
+
+/// Does a motif contain any IUPAC ambiguity codes?
+pub fn is_degenerate(motif: &str) -> bool {
+    motif
+        .bytes()
+        .any(|b| !matches!(b, b'A' | b'C' | b'G' | b'T'))
+}
+
+/// Uppercase a telomeric repeat given on the command line, and check
+/// it only contains valid IUPAC nucleotide codes.
+pub fn parse_telomeric_repeat(repeat: &str) -> Result<String> {
+    let repeat = repeat.to_uppercase();
+    if repeat.is_empty() {
+        bail!("The telomeric repeat search string is empty.")
+    }
+    if let Some(c) = repeat
+        .chars()
+        .find(|c| !c.is_ascii() || iupac_mask(*c as u8) == 0)
+    {
+        bail!(
+            "The telomeric repeat {} contains '{}', which is not an IUPAC nucleotide code.",
+            repeat,
+            c
+        )
+    }
+    Ok(repeat)
+}
+
+/// Is a telomeric repeat the G-rich strand of the telomere, i.e.
+/// does it have at least as many G's as C's?
+pub fn is_g_rich(telomeric_repeat: &str) -> bool {
+    let g = telomeric_repeat.matches('G').count();
+    let c = telomeric_repeat.matches('C').count();
+    g >= c
+}
+
 /// Calculate the reverse complement of a telomeric repeat.
 pub fn reverse_complement(dna: &str) -> String {
     let dna_chars = dna.chars();
@@ -45,38 +414,108 @@ pub fn reverse_complement(dna: &str) -> String {
     revcomp.into_iter().collect()
 }
 
-/// Switch complementary bases in a DNA string.
+/// Switch complementary bases in a DNA string. Ambiguity codes
+/// are complemented too (e.g. R, A or G, becomes Y, C or T).
 fn switch_base(c: char) -> char {
     match c {
         'A' => 'T',
         'C' => 'G',
         'T' => 'A',
         'G' => 'C',
+        'R' => 'Y',
+        'Y' => 'R',
+        'K' => 'M',
+        'M' => 'K',
+        'S' => 'S',
+        'W' => 'W',
+        'B' => 'V',
+        'V' => 'B',
+        'D' => 'H',
+        'H' => 'D',
         'N' => 'N',
         _ => 'N',
     }
 }
 
-/// Not sure if this function is necessary, but it looks at the indexes of the motifs
-/// in the genome and removes indexes which occur consecutively less than the pattern
-/// length apart.
+/// Looks at the indexes of the motifs in the genome and removes indexes
+/// which occur less than the pattern length after the last kept index,
+/// so each base is counted as part of at most one repeat unit.
 pub fn remove_overlapping_indexes(indexes: Motifs, pattern_length: usize) -> Vec<usize> {
-    let mut indexes = indexes.indexes;
-    let mut index = 0;
-    let mut vec_len;
+    let mut kept: Vec<usize> = Vec::with_capacity(indexes.length);
 
-    loop {
-        vec_len = indexes.len();
+    for index in indexes.indexes {
+        match kept.last() {
+            Some(last) if index < last + pattern_length => continue,
+            _ => kept.push(index),
+        }
+    }
+    kept
+}
 
-        if indexes.is_empty() || index == 0 || index == vec_len - 1 {
-            break;
+/// Keep only the repeat units which sit directly next to another unit,
+/// i.e. which are part of a perfect tandem array of at least two units.
+/// Overlapping matches are removed first.
+pub fn tandem_indexes(indexes: Motifs, pattern_length: usize) -> Vec<usize> {
+    let indexes = remove_overlapping_indexes(indexes, pattern_length);
+
+    indexes
+        .iter()
+        .enumerate()
+        .filter(|(i, index)| {
+            let previous_adjacent = *i > 0 && indexes[i - 1] + pattern_length == **index;
+            let next_adjacent = indexes
+                .get(i + 1)
+                .is_some_and(|next| **index + pattern_length == *next);
+            previous_adjacent || next_adjacent
+        })
+        .map(|(_, index)| *index)
+        .collect()
+}
+
+/// Which of the matches of a telomeric repeat are counted. All but
+/// [`CountMode::Overlapping`] take every match to be as long as the
+/// repeat, which isn't true of matches with insertions or deletions,
+/// so with [`Tolerance::Edits`] their counts are only approximate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CountMode {
+    /// Every match, even if it overlaps another. This is
+    /// what `tidk` has always counted.
+    Overlapping,
+    /// Matches which do not overlap a previously counted match.
+    NonOverlapping,
+    /// Non-overlapping matches which are directly adjacent
+    /// to another match.
+    Tandem,
+}
+
+impl CountMode {
+    /// Parse the `--count-mode` value from the command line.
+    pub fn new(mode: &str) -> Self {
+        match mode {
+            "non-overlapping" => CountMode::NonOverlapping,
+            "tandem" => CountMode::Tandem,
+            _ => CountMode::Overlapping,
         }
-        while indexes[index + 1] < indexes[index] + pattern_length {
-            indexes.remove(index + 1);
-            index += 1;
+    }
+
+    /// Filter the matches of a motif according to the count mode.
+    pub fn apply(&self, motifs: Motifs, pattern_length: usize) -> Vec<usize> {
+        match self {
+            CountMode::Overlapping => motifs.indexes,
+            CountMode::NonOverlapping => remove_overlapping_indexes(motifs, pattern_length),
+            CountMode::Tandem => tandem_indexes(motifs, pattern_length),
+        }
+    }
+}
+
+impl std::fmt::Display for CountMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CountMode::Overlapping => write!(f, "overlapping"),
+            CountMode::NonOverlapping => write!(f, "non-overlapping"),
+            CountMode::Tandem => write!(f, "tandem"),
         }
     }
-    indexes
 }
 
 /// A string rotation algorithm.
@@ -160,11 +599,59 @@ pub fn lex_min(dna_string: &str) -> String {
     // string
     let lms_f = format!("{}{}", start_f, end_f);
     let lms_r = format!("{}{}", start_r, end_r);
-    let mut strings = vec![&lms_f, &lms_r];
+    let mut strings = [&lms_f, &lms_r];
     strings.string_sort_unstable(natural_lexical_cmp);
     strings[0].to_string()
 }
 
+/// A file in the temporary directory for a test, removed (along with
+/// any companions, such as its index) when it goes out of scope, even if
+/// the test fails.
+#[cfg(test)]
+pub struct TempFile {
+    pub path: std::path::PathBuf,
+    companions: Vec<std::path::PathBuf>,
+}
+
+#[cfg(test)]
+impl TempFile {
+    /// A new path ending in `name`, unique to this test run, as tests run
+    /// in parallel.
+    pub fn new(name: &str) -> Self {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "tidk-{}-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed),
+            name
+        ));
+        TempFile {
+            path,
+            companions: Vec::new(),
+        }
+    }
+
+    /// The path of the file with `suffix` added, e.g. `.fai`, which is
+    /// removed along with the file.
+    pub fn companion(&mut self, suffix: &str) -> std::path::PathBuf {
+        let mut path = self.path.as_os_str().to_owned();
+        path.push(suffix);
+        let path = std::path::PathBuf::from(path);
+        self.companions.push(path.clone());
+        path
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        for path in std::iter::once(&self.path).chain(&self.companions) {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -233,7 +720,155 @@ mod tests {
 
     #[test]
     fn motifs1() {
-        let motifs = find_motifs(CANONICAL, HAYSTACK);
+        let motifs = find_motifs(CANONICAL, HAYSTACK.as_bytes());
         assert_eq!(motifs.indexes, EXPECTED)
     }
+
+    // 3*TTAGGG, with a variant TTGGGG unit in the middle
+    const VARIANT_HAYSTACK: &str = "TTAGGGTTGGGGTTAGGG";
+
+    fn motifs(indexes: &[usize]) -> Motifs {
+        Motifs {
+            indexes: indexes.to_vec(),
+            length: indexes.len(),
+        }
+    }
+
+    #[test]
+    fn overlapping_removed() {
+        // AAAA matches at every position of a run of A's
+        let found = find_motifs("AAAA", b"AAAAAAAAAC");
+        assert_eq!(found.indexes, vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(remove_overlapping_indexes(found, 4), vec![0, 4]);
+    }
+
+    #[test]
+    fn tandem_only() {
+        // two adjacent units, a lone unit, then three adjacent units
+        let found = motifs(&[0, 5, 17, 30, 35, 40]);
+        assert_eq!(tandem_indexes(found, 5), vec![0, 5, 30, 35, 40]);
+    }
+
+    #[test]
+    fn count_modes() {
+        let found = || motifs(&[0, 2, 5, 17]);
+        assert_eq!(CountMode::Overlapping.apply(found(), 5), vec![0, 2, 5, 17]);
+        assert_eq!(CountMode::NonOverlapping.apply(found(), 5), vec![0, 5, 17]);
+        assert_eq!(CountMode::Tandem.apply(found(), 5), vec![0, 5]);
+    }
+
+    #[test]
+    fn motifs_mismatches() {
+        let exact =
+            find_motifs_with_tolerance("TTAGGG", VARIANT_HAYSTACK.as_bytes(), Tolerance::Exact)
+                .unwrap();
+        assert_eq!(exact.indexes, vec![0, 12]);
+
+        let one = find_motifs_with_tolerance(
+            "TTAGGG",
+            VARIANT_HAYSTACK.as_bytes(),
+            Tolerance::Mismatches(1),
+        )
+        .unwrap();
+        assert_eq!(one.indexes, vec![0, 6, 12]);
+    }
+
+    #[test]
+    fn revcomp_iupac() {
+        assert_eq!(reverse_complement("TTRGGG"), "CCCYAA");
+        assert_eq!(reverse_complement("BDHVKMSWN"), "NWSKMBDHV");
+    }
+
+    #[test]
+    fn motifs_iupac() {
+        // TTAGGG and TTGGGG are both counted by TTRGGG
+        let motifs = find_motifs("TTRGGG", VARIANT_HAYSTACK.as_bytes());
+        assert_eq!(motifs.indexes, vec![0, 6, 12]);
+    }
+
+    #[test]
+    fn windows_tiled() {
+        let windows = window_coordinates(25, 10, 10);
+        assert_eq!(windows, vec![(0, 10), (10, 20), (20, 25)]);
+    }
+
+    #[test]
+    fn windows_sliding() {
+        let windows = window_coordinates(25, 10, 5);
+        assert_eq!(windows, vec![(0, 10), (5, 15), (10, 20), (15, 25)]);
+    }
+
+    #[test]
+    fn windows_count() {
+        let windows = window_coordinates(25, 10, 5);
+        let counts = count_in_windows(&[0, 4, 9, 12, 24], &windows);
+        assert_eq!(counts, vec![3, 2, 1, 1]);
+    }
+
+    #[test]
+    fn expand_motif() {
+        assert_eq!(
+            expand_iupac("TTRGG", 10).unwrap(),
+            vec!["TTAGG".to_string(), "TTGGG".to_string()]
+        );
+        assert_eq!(expand_iupac("NNNNNN", 10), None);
+    }
+
+    #[test]
+    fn multi_motifs() {
+        // a repeat, its reverse complement, and a palindrome
+        let motifs = ["TTRGGG", "CCCYAA", "GGGCCC"];
+        let matcher = MultiMotifs::new(&motifs).unwrap();
+        let found = matcher.find_all(b"TTAGGGTTGGGGCCCTAAGGGCCC");
+
+        for (motif, multi) in motifs.iter().zip(&found) {
+            let single = find_motifs(motif, b"TTAGGGTTGGGGCCCTAAGGGCCC");
+            assert_eq!(multi.indexes, single.indexes);
+        }
+        assert_eq!(found[0].indexes, vec![0, 6]);
+        assert_eq!(found[1].indexes, vec![12]);
+        assert_eq!(found[2].indexes, vec![9, 18]);
+    }
+
+    #[test]
+    fn parse_repeat() {
+        assert_eq!(parse_telomeric_repeat("ttrggg").unwrap(), "TTRGGG");
+        assert!(parse_telomeric_repeat("TTAGGX").is_err());
+        assert!(parse_telomeric_repeat("").is_err());
+    }
+
+    #[test]
+    fn motifs_edits() {
+        // the deletion in the haystack is only picked up with edits
+        let motifs =
+            find_motifs_with_tolerance(CANONICAL, HAYSTACK.as_bytes(), Tolerance::Edits(1))
+                .unwrap();
+        assert_eq!(motifs.length, 8);
+        assert_eq!(&motifs.indexes[..6], &EXPECTED[..6]);
+    }
+
+    #[test]
+    fn motifs_edits_tandem() {
+        // with two edits the hits of neighbouring units run together,
+        // but each unit is still counted once
+        let tract = "TTAGG".repeat(10);
+        let motifs =
+            find_motifs_with_tolerance("TTAGG", tract.as_bytes(), Tolerance::Edits(2)).unwrap();
+        assert_eq!(motifs.length, 10);
+
+        // a deleted base near the end of a run, and an inserted one
+        // near the start of the next
+        let indels = "TTAGGTTAGTTAGGATTAGGTTAGG";
+        for edits in 1..=2 {
+            let motifs =
+                find_motifs_with_tolerance("TTAGG", indels.as_bytes(), Tolerance::Edits(edits))
+                    .unwrap();
+            assert!(motifs.indexes.windows(2).all(|w| w[0] < w[1]));
+            assert_eq!(motifs.length, motifs.indexes.len());
+        }
+
+        // Myers' algorithm only works on repeats up to the word size
+        let long = "TTAGG".repeat(13);
+        assert!(find_motifs_with_tolerance(&long, tract.as_bytes(), Tolerance::Edits(1)).is_err());
+    }
 }
@@ -0,0 +1,785 @@
+use crate::{agp, fusion, reader, tracts, utils};
+use anyhow::{bail, Result};
+use bio::io::fasta;
+use std::fs::{create_dir_all, File};
+use std::io::{LineWriter, Write};
+use std::path::PathBuf;
+
+/// The format of the window counts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// The forward and reverse counts of each telomeric repeat in each
+    /// window, by window end.
+    Tsv,
+    /// The start and end of each window, and the sum of the counts on
+    /// both strands of all the telomeric repeats.
+    Bedgraph,
+}
+
+impl OutputFormat {
+    /// Parse the format from the command line.
+    pub fn new(extension: &str) -> Self {
+        match extension {
+            "bedgraph" => OutputFormat::Bedgraph,
+            _ => OutputFormat::Tsv,
+        }
+    }
+
+    /// The extension of the windows file.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Tsv => "tsv",
+            OutputFormat::Bedgraph => "bedgraph",
+        }
+    }
+}
+
+/// The forward and reverse counts of a telomeric repeat in each
+/// window of a record, as written by `tidk search` and `tidk find`.
+#[derive(Debug, Clone)]
+pub struct WindowCounts {
+    /// The telomeric repeat, as queried.
+    pub telomeric_repeat: String,
+    /// The 0-based start and exclusive end of each window.
+    pub windows: Vec<(usize, usize)>,
+    /// The forward repeat number in each window.
+    pub forward: Vec<usize>,
+    /// The reverse repeat number in each window.
+    pub reverse: Vec<usize>,
+}
+
+/// How the telomeric repeats are counted in windows.
+#[derive(Debug, Clone, Copy)]
+pub struct WindowParams {
+    pub window_size: usize,
+    /// The bases between the starts of consecutive windows, so
+    /// they overlap if this is less than the window size.
+    pub step: usize,
+    pub format: OutputFormat,
+    pub tolerance: utils::Tolerance,
+    pub count_mode: utils::CountMode,
+    /// If given, the telomeric tracts are called too.
+    pub tract_params: Option<tracts::TractParams>,
+}
+
+impl WindowParams {
+    /// Get the window options from the command line.
+    pub fn from_matches(matches: &clap::ArgMatches) -> Result<Self> {
+        let window_size = *matches
+            .get_one::<usize>("window")
+            .expect("defaulted by clap");
+        // by default, windows do not overlap
+        let step = *matches.get_one::<usize>("step").unwrap_or(&window_size);
+        if step == 0 || window_size == 0 {
+            bail!("The window size and step must both be greater than zero.")
+        }
+        // the tracts are needed for the tracts, the ends summary
+        // and the interstitial tracts
+        let tract_params = if matches.get_flag("tracts")
+            || matches.get_flag("ends-summary")
+            || matches.get_flag("its")
+        {
+            Some(tracts::TractParams {
+                max_gap: *matches
+                    .get_one::<usize>("max-gap")
+                    .expect("defaulted by clap"),
+                min_length: *matches
+                    .get_one::<usize>("min-tract-length")
+                    .expect("defaulted by clap"),
+            })
+        } else {
+            None
+        };
+        Ok(WindowParams {
+            window_size,
+            step,
+            format: OutputFormat::new(
+                matches
+                    .get_one::<String>("extension")
+                    .expect("defaulted by clap"),
+            ),
+            tolerance: utils::Tolerance::new(
+                matches.get_one::<u8>("mismatches").copied(),
+                matches.get_one::<u8>("edits").copied(),
+            ),
+            count_mode: utils::CountMode::new(
+                matches
+                    .get_one::<String>("count-mode")
+                    .expect("defaulted by clap"),
+            ),
+            tract_params,
+        })
+    }
+}
+
+/// Count the telomeric repeats in windows along each record of the
+/// input, and write the window counts, and any of the tracts, the
+/// ends summary, the interstitial tracts and the fusions asked for.
+/// This is the whole of `tidk search` and `tidk find`, which only
+/// differ in where the telomeric repeats come from.
+pub fn count_windows(matches: &clap::ArgMatches, telomeric_repeats: &[&str]) -> Result<()> {
+    let input_fasta = matches
+        .get_one::<PathBuf>("fasta")
+        .expect("errored by clap");
+    let params = WindowParams::from_matches(matches)?;
+    if params.tolerance != utils::Tolerance::Exact {
+        eprintln!(
+            "[+]\tCounting approximate matches with {}",
+            params.tolerance
+        );
+    }
+
+    let regions = reader::regions_from_matches(matches)?;
+    let options = reader::AlignmentOptions::from_matches(matches);
+    // only scan the ends of each record
+    let ends_only = matches
+        .get_one::<String>("ends-only")
+        .map(|length| reader::EndLength::parse(length))
+        .transpose()?;
+    let records = match ends_only {
+        Some(end_length) => {
+            eprintln!(
+                "[+]\tScanning only {} at each end of each record",
+                end_length
+            );
+            reader::end_region_records(input_fasta, end_length, &options)?
+        }
+        None => reader::region_records(input_fasta, &regions, &options)?,
+    };
+
+    // the prefix of the output files, which can be left out if
+    // only the window counts are written, to stdout
+    let prefix = match (
+        matches.get_one::<PathBuf>("dir"),
+        matches.get_one::<PathBuf>("output"),
+    ) {
+        (Some(outdir), Some(output)) => {
+            // create directory for output
+            create_dir_all(outdir)?;
+            Some(format!("{}/{}", outdir.display(), output.display()))
+        }
+        _ => None,
+    };
+    let create_file = |suffix: &str| -> Result<File> {
+        match &prefix {
+            Some(prefix) => Ok(File::create(format!("{}{}", prefix, suffix))?),
+            None => bail!(
+                "Writing the {} file needs --dir and --output, only the window counts can go to stdout.",
+                suffix
+            ),
+        }
+    };
+
+    // create file
+    let mut windows_file: LineWriter<Box<dyn Write>> = if matches.get_flag("stdout") {
+        LineWriter::new(Box::new(std::io::stdout()))
+    } else {
+        LineWriter::new(Box::new(create_file(&format!(
+            "_telomeric_repeat_windows.{}",
+            params.format.extension()
+        ))?))
+    };
+    // add headers if extension/file type is a tsv
+    if params.format == OutputFormat::Tsv {
+        writeln!(
+            windows_file,
+            "id\twindow\tforward_repeat_number\treverse_repeat_number\ttelomeric_repeat{}",
+            if ends_only.is_some() { "\tend" } else { "" }
+        )?;
+    }
+
+    // optional file of telomeric tracts, summary of the telomeres at the
+    // ends of each record, and interstitial tracts, which all need the tracts.
+    let max_end_distance = *matches
+        .get_one::<usize>("max-end-distance")
+        .expect("defaulted by clap");
+    let min_end_repeats = *matches
+        .get_one::<usize>("min-end-repeats")
+        .expect("defaulted by clap");
+    let its_distance = *matches
+        .get_one::<usize>("its-distance")
+        .expect("defaulted by clap");
+    // optional file of inverted junctions of telomeric arrays, which
+    // are found from the window counts
+    let fusion_params = if matches.get_flag("fusions") {
+        Some(fusion::FusionParams {
+            min_repeats: *matches
+                .get_one::<usize>("fusion-min-repeats")
+                .expect("defaulted by clap"),
+            min_distance: its_distance,
+        })
+    } else {
+        None
+    };
+    // lift the tracts between contigs and scaffolds
+    let agp = matches
+        .get_one::<PathBuf>("agp")
+        .map(agp::Agp::from_path)
+        .transpose()?;
+    let mut tract_file = if matches.get_flag("tracts") {
+        let mut tract_file = LineWriter::new(create_file("_telomeric_tracts.bed")?);
+        if agp.is_some() {
+            writeln!(tract_file, "{}", agp::LIFTED_TRACTS_HEADER)?;
+        }
+        Some(tract_file)
+    } else {
+        None
+    };
+    let mut ends_file = if matches.get_flag("ends-summary") {
+        let mut ends_file = LineWriter::new(create_file("_telomere_ends.tsv")?);
+        writeln!(ends_file, "{}", tracts::TELOMERE_ENDS_HEADER)?;
+        Some(ends_file)
+    } else {
+        None
+    };
+    let mut its_file = if matches.get_flag("its") {
+        let mut its_file = LineWriter::new(create_file("_interstitial_tracts.bed")?);
+        writeln!(its_file, "{}", tracts::INTERSTITIAL_HEADER)?;
+        Some(its_file)
+    } else {
+        None
+    };
+    let mut fusions_file = if fusion_params.is_some() {
+        let mut fusions_file = LineWriter::new(create_file("_fusions.tsv")?);
+        writeln!(fusions_file, "{}", fusion::FUSIONS_HEADER)?;
+        Some(fusions_file)
+    } else {
+        None
+    };
+
+    // iterate over the fasta records
+    for result in records {
+        let region = result?;
+        let id = region.record.id().to_owned();
+        let record_length = region.record_length;
+
+        // fn window counter
+        let (record_tracts, counts) = write_window_counts(
+            &region.record,
+            &mut windows_file,
+            telomeric_repeats,
+            region.offset,
+            ends_only.map(|_| record_length),
+            &params,
+        )?;
+
+        if let Some(f) = tract_file.as_mut() {
+            for tract in &record_tracts {
+                let Some(agp) = &agp else {
+                    writeln!(f, "{}", tract.to_bed())?;
+                    continue;
+                };
+                let lifted = agp.lift_tract(tract, max_end_distance);
+                if let Some(l) = lifted
+                    .as_ref()
+                    .filter(|l| l.position == agp::Position::Internal)
+                {
+                    eprintln!(
+                        "[-]\tThe telomeric tract at {}:{}-{} ({}:{}-{}) is at the end of a contig, but inside its scaffold",
+                        tract.id, tract.start, tract.end, l.id, l.start, l.end
+                    );
+                }
+                writeln!(
+                    f,
+                    "{}\t{}",
+                    tract.to_bed(),
+                    agp::lifted_columns(lifted.as_ref())
+                )?;
+            }
+        }
+        if let Some(f) = ends_file.as_mut() {
+            for mut end in
+                tracts::telomere_ends(&id, record_length, &record_tracts, max_end_distance)
+            {
+                // only the ends inside the region are summarised
+                if !region.reaches(end.end) {
+                    continue;
+                }
+                end.add_window_counts(&counts, record_length, max_end_distance, min_end_repeats);
+                if end.orientation() == "inverted" {
+                    eprintln!(
+                        "[-]\tThe {} end of {} looks inverted, with more repeat units on the strand expected at the other end",
+                        end.end, id
+                    );
+                }
+                writeln!(f, "{}", end.to_tsv())?;
+            }
+        }
+        if let Some(f) = its_file.as_mut() {
+            for (tract, distance) in
+                tracts::interstitial_tracts(&record_tracts, record_length, its_distance)
+            {
+                writeln!(f, "{}\t{}\t{}", tract.to_bed(), tract.length(), distance)?;
+            }
+        }
+        if let (Some(f), Some(params)) = (fusions_file.as_mut(), fusion_params) {
+            for repeat_counts in &counts {
+                for fusion in fusion::find_fusions(&id, repeat_counts, record_length, params) {
+                    writeln!(f, "{}", fusion.to_tsv())?;
+                }
+            }
+        }
+
+        eprintln!("[+]\tChromosome {} processed", id);
+    }
+
+    Ok(())
+}
+
+/// Count the telomeric repeats in windows along a record. All the
+/// telomeric repeats are counted on both strands in a single pass over
+/// the record.
+///
+/// If `tract_params` are given, the telomeric tracts of every repeat on
+/// both strands of the record are returned, sorted by start. The window
+/// counts of each telomeric repeat are returned too.
+///
+/// If the sequence is a region of a record, `offset` is where it starts
+/// on the record, and everything returned is in record coordinates.
+pub fn count_record(
+    sequence: &fasta::Record,
+    telomeric_repeats: &[&str],
+    offset: usize,
+    params: &WindowParams,
+) -> Result<(Vec<tracts::Tract>, Vec<WindowCounts>)> {
+    let id = sequence.id();
+
+    // the start and end of each window
+    let windows = utils::window_coordinates(sequence.seq().len(), params.window_size, params.step);
+
+    // search the whole record at once, so repeats that cross window
+    // boundaries are still found.
+    let motifs = find_telomeric_repeats(sequence.seq(), telomeric_repeats, params.tolerance)?;
+
+    // merge all the matches into tracts
+    let mut record_tracts = match params.tract_params {
+        Some(tract_params) => tracts::call_record_tracts(
+            id,
+            telomeric_repeats,
+            &motifs,
+            params.count_mode,
+            tract_params,
+        ),
+        None => Vec::new(),
+    };
+    // back to the coordinates of the whole record
+    for tract in &mut record_tracts {
+        tract.start += offset;
+        tract.end += offset;
+    }
+    let record_windows: Vec<(usize, usize)> = windows
+        .iter()
+        .map(|(start, end)| (start + offset, end + offset))
+        .collect();
+
+    // forward and reverse counts for each telomeric repeat, in each window.
+    let mut counts = Vec::new();
+
+    for (forward_telomeric_seq, (forward_motif, reverse_motif)) in
+        telomeric_repeats.iter().zip(motifs)
+    {
+        let telomeric_length = forward_telomeric_seq.len();
+
+        // keep the matches the count mode asks for
+        let forward_motif_counted = params.count_mode.apply(forward_motif, telomeric_length);
+        let reverse_motif_counted = params.count_mode.apply(reverse_motif, telomeric_length);
+
+        // the number of matches for forward/reverse in each window,
+        // by where each match starts
+        counts.push(WindowCounts {
+            telomeric_repeat: forward_telomeric_seq.to_string(),
+            forward: utils::count_in_windows(&forward_motif_counted, &windows),
+            reverse: utils::count_in_windows(&reverse_motif_counted, &windows),
+            windows: record_windows.clone(),
+        });
+    }
+
+    Ok((record_tracts, counts))
+}
+
+/// Write the window counts of a record as TSV rows, one telomeric repeat
+/// at a time, each row starting with `sample` if there is one. If
+/// `record_length` is given, each row ends with the end of the record
+/// its window is nearer.
+pub fn write_tsv_rows<T: Write>(
+    file: &mut T,
+    sample: Option<&str>,
+    id: &str,
+    counts: &[WindowCounts],
+    record_length: Option<usize>,
+) -> Result<()> {
+    for repeat_counts in counts {
+        for ((start, end), (forward_repeat_number, reverse_repeat_number)) in repeat_counts
+            .windows
+            .iter()
+            .zip(repeat_counts.forward.iter().zip(&repeat_counts.reverse))
+        {
+            if let Some(sample) = sample {
+                write!(file, "{}\t", sample)?;
+            }
+            write!(
+                file,
+                "{}\t{}\t{}\t{}\t{}",
+                id,
+                end,
+                forward_repeat_number,
+                reverse_repeat_number,
+                repeat_counts.telomeric_repeat
+            )?;
+            match record_length {
+                Some(record_length) => writeln!(
+                    file,
+                    "\t{}",
+                    tracts::End::nearer(*start, *end, record_length)
+                )?,
+                None => writeln!(file)?,
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Count the telomeric repeats in windows along a record, and write the
+/// counts to `file` as they are counted. In TSV the counts are written
+/// one telomeric repeat at a time.
+///
+/// The tracts and window counts of [`count_record`] are returned. If
+/// `record_length` is given, each TSV line ends with the end of the
+/// record its window is nearer.
+pub fn write_window_counts<T: Write>(
+    sequence: &fasta::Record,
+    file: &mut T,
+    telomeric_repeats: &[&str],
+    offset: usize,
+    record_length: Option<usize>,
+    params: &WindowParams,
+) -> Result<(Vec<tracts::Tract>, Vec<WindowCounts>)> {
+    let id = sequence.id();
+    let (record_tracts, counts) = count_record(sequence, telomeric_repeats, offset, params)?;
+
+    match params.format {
+        OutputFormat::Tsv => write_tsv_rows(file, None, id, &counts, record_length)?,
+        // for bedgraph only four columns, and sum the forward & reverse
+        // of every telomeric repeat for convenience
+        OutputFormat::Bedgraph => {
+            for (i, (start, end)) in counts
+                .first()
+                .map(|c| c.windows.as_slice())
+                .unwrap_or_default()
+                .iter()
+                .enumerate()
+            {
+                let total: usize = counts.iter().map(|c| c.forward[i] + c.reverse[i]).sum();
+                writeln!(file, "{}\t{}\t{}\t{}", id, start, end, total)?;
+            }
+        }
+    }
+
+    Ok((record_tracts, counts))
+}
+
+/// Finds the matches of several telomeric repeats on both strands of a
+/// sequence. Build it once to search many sequences for the same repeats.
+pub struct TelomericMatcher {
+    /// Forward and reverse sequences of each telomeric repeat, alternating.
+    query: Vec<String>,
+    /// The automaton for exact matching, if it could be built.
+    multi_motifs: Option<utils::MultiMotifs>,
+    /// How approximate matches are searched for without the automaton.
+    tolerance: utils::Tolerance,
+}
+
+impl TelomericMatcher {
+    /// Build the matcher for some telomeric repeats.
+    pub fn new(telomeric_repeats: &[&str], tolerance: utils::Tolerance) -> Self {
+        // forward and reverse sequences alternate in here.
+        let mut query = Vec::new();
+        for forward_telomeric_seq in telomeric_repeats {
+            query.push(forward_telomeric_seq.to_string());
+            query.push(utils::reverse_complement(forward_telomeric_seq));
+        }
+        let query_str: Vec<&str> = query.iter().map(|e| e.as_str()).collect();
+
+        // approximate matching can't be done with the automaton, so each
+        // telomeric repeat is then searched for separately.
+        let multi_motifs = match tolerance {
+            utils::Tolerance::Exact => utils::MultiMotifs::new(&query_str),
+            _ => None,
+        };
+
+        Self {
+            query,
+            multi_motifs,
+            tolerance,
+        }
+    }
+
+    /// Find the matches in a sequence. Exact matches of all the repeats
+    /// are found in a single pass over the sequence.
+    ///
+    /// Returns the forward and reverse strand matches of each telomeric
+    /// repeat, in the order the repeats were given.
+    pub fn find(&self, sequence: &[u8]) -> Result<Vec<(utils::Motifs, utils::Motifs)>> {
+        // soft-masked bases are matched too
+        let sequence_upper = sequence.to_ascii_uppercase();
+        let motifs: Vec<utils::Motifs> = match &self.multi_motifs {
+            Some(m) => m.find_all(&sequence_upper),
+            None => self
+                .query
+                .iter()
+                .map(|q| utils::find_motifs_with_tolerance(q, &sequence_upper, self.tolerance))
+                .collect::<Result<_>>()?,
+        };
+
+        let mut motifs = motifs.into_iter();
+        let mut strands = Vec::new();
+        while let (Some(forward), Some(reverse)) = (motifs.next(), motifs.next()) {
+            strands.push((forward, reverse));
+        }
+        Ok(strands)
+    }
+}
+
+/// Find the matches of several telomeric repeats on both strands of a
+/// sequence, with a [`TelomericMatcher`] built for just this sequence.
+pub fn find_telomeric_repeats(
+    sequence: &[u8],
+    telomeric_repeats: &[&str],
+    tolerance: utils::Tolerance,
+) -> Result<Vec<(utils::Motifs, utils::Motifs)>> {
+    TelomericMatcher::new(telomeric_repeats, tolerance).find(sequence)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{CountMode, Tolerance};
+
+    // a wrapper for making a bio::io::fasta record
+    fn make_record(id: &str, seq: &[u8]) -> fasta::Record {
+        fasta::Record::with_attrs(id, None, seq)
+    }
+
+    fn params(ws: usize, step: usize, tolerance: Tolerance, format: OutputFormat) -> WindowParams {
+        WindowParams {
+            window_size: ws,
+            step,
+            format,
+            tolerance,
+            count_mode: CountMode::Overlapping,
+            tract_params: None,
+        }
+    }
+
+    // take a record, write to a vector (fake file), then read out of this the output.
+    fn calc_windows(
+        rec: fasta::Record,
+        repeats: &[&str],
+        ws: usize,
+        step: usize,
+        tolerance: Tolerance,
+    ) -> String {
+        let mut out = Vec::new();
+        write_window_counts(
+            &rec,
+            &mut out,
+            repeats,
+            0,
+            None,
+            &params(ws, step, tolerance, OutputFormat::Tsv),
+        )
+        .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_search_1() {
+        let rec = make_record(
+            "test1",
+            b"TTAGGTTAGGTTAGGCAGCATCACACTGATCATCTGATTAGGTTAGGTTAGG",
+        );
+
+        let windows_calculation = calc_windows(rec, &["TTAGG"], 20, 20, Tolerance::Exact);
+
+        let rows: Vec<&str> = windows_calculation.lines().collect();
+
+        // three in first window
+        assert_eq!(rows[0], "test1\t20\t3\t0\tTTAGG");
+        // one in second, starting at 37 and crossing into the third
+        assert_eq!(rows[1], "test1\t40\t1\t0\tTTAGG");
+        // two in third
+        assert_eq!(rows[2], "test1\t52\t2\t0\tTTAGG");
+    }
+
+    #[test]
+    fn test_search_mismatches() {
+        // the middle unit is a TTGGG variant
+        let rec = make_record("test2", b"TTAGGTTGGGTTAGGCAGCATCACACTGATCATCTGA");
+
+        let exact = calc_windows(rec.clone(), &["TTAGG"], 20, 20, Tolerance::Exact);
+        assert_eq!(exact.lines().next().unwrap(), "test2\t20\t2\t0\tTTAGG");
+
+        let approximate = calc_windows(rec, &["TTAGG"], 20, 20, Tolerance::Mismatches(1));
+        assert_eq!(
+            approximate.lines().next().unwrap(),
+            "test2\t20\t3\t0\tTTAGG"
+        );
+    }
+
+    #[test]
+    fn test_search_iupac() {
+        let rec = make_record("test3", b"TTAGGTTGGGTTAGGCAGCACCCAACCTAACATCTGA");
+
+        let windows_calculation = calc_windows(rec, &["TTRGG"], 20, 20, Tolerance::Exact);
+        let rows: Vec<&str> = windows_calculation.lines().collect();
+
+        // TTAGG and TTGGG on the forward strand, CCTAA and CCCAA on the reverse
+        assert_eq!(rows[0], "test3\t20\t3\t0\tTTRGG");
+        assert_eq!(rows[1], "test3\t37\t0\t2\tTTRGG");
+    }
+
+    #[test]
+    fn test_search_step() {
+        let rec = make_record(
+            "test4",
+            b"TTAGGTTAGGTTAGGCAGCATCACACTGATCATCTGATTAGGTTAGGTTAGG",
+        );
+
+        let windows_calculation = calc_windows(rec, &["TTAGG"], 20, 10, Tolerance::Exact);
+        let rows: Vec<&str> = windows_calculation.lines().collect();
+
+        // windows end every 10 bases, the last one at the end of the sequence
+        assert_eq!(
+            rows,
+            vec![
+                "test4\t20\t3\t0\tTTAGG",
+                "test4\t30\t1\t0\tTTAGG",
+                "test4\t40\t1\t0\tTTAGG",
+                "test4\t50\t3\t0\tTTAGG",
+                "test4\t52\t2\t0\tTTAGG",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_search_boundary() {
+        // the second and fourth units cross the window boundaries at 7 and 14
+        let rec = make_record("test5", b"TTAGGTTAGGTTAGGTTAGGA");
+
+        let windows_calculation = calc_windows(rec, &["TTAGG"], 7, 7, Tolerance::Exact);
+        let rows: Vec<&str> = windows_calculation.lines().collect();
+
+        assert_eq!(
+            rows,
+            vec![
+                "test5\t7\t2\t0\tTTAGG",
+                "test5\t14\t1\t0\tTTAGG",
+                "test5\t21\t1\t0\tTTAGG",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_search_offset() {
+        // a region starting 100 bases into its record
+        let rec = make_record("test6", b"TTAGGTTAGGTTAGGCAGCA");
+        let mut out = Vec::new();
+        let (_, counts) = write_window_counts(
+            &rec,
+            &mut out,
+            &["TTAGG"],
+            100,
+            None,
+            &params(10, 10, Tolerance::Exact, OutputFormat::Bedgraph),
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "test6\t100\t110\t2\ntest6\t110\t120\t1\n"
+        );
+        assert_eq!(counts[0].windows, vec![(100, 110), (110, 120)]);
+    }
+
+    #[test]
+    fn test_search_ends_only() {
+        // the 3' end of a 1000 base record
+        let rec = make_record("test7", b"CAGCACCTAACCTAACCTAA");
+        let mut out = Vec::new();
+        write_window_counts(
+            &rec,
+            &mut out,
+            &["TTAGG"],
+            980,
+            Some(1000),
+            &params(10, 10, Tolerance::Exact, OutputFormat::Tsv),
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "test7\t990\t0\t1\tTTAGG\t3'\ntest7\t1000\t0\t2\tTTAGG\t3'\n"
+        );
+    }
+
+    #[test]
+    fn test_find_1() {
+        let rec = make_record(
+            "test1",
+            b"AAACCCTAAACCCTAAACCCTTGAGAGAGGGGGTGTGGGGAGGGGTTGAGAAACCCT",
+        );
+
+        let windows_calculation = calc_windows(rec, &["AAACCCT"], 20, 20, Tolerance::Exact);
+
+        let rows: Vec<&str> = windows_calculation.lines().collect();
+
+        // three in first window, the last one crossing into the second
+        assert_eq!(rows[0], "test1\t20\t3\t0\tAAACCCT");
+        // none in second
+        assert_eq!(rows[1], "test1\t40\t0\t0\tAAACCCT");
+        // one in third
+        assert_eq!(rows[2], "test1\t57\t1\t0\tAAACCCT");
+    }
+
+    #[test]
+    fn test_find_multiple_repeats() {
+        let rec = make_record(
+            "test2",
+            b"TTAGGTTAGGTCAGGTCAGGCAGCATCACACTGATCATCTGACCTGACCTGA",
+        );
+
+        let windows_calculation =
+            calc_windows(rec.clone(), &["TTAGG", "TCAGG"], 20, 20, Tolerance::Exact);
+
+        let rows: Vec<&str> = windows_calculation.lines().collect();
+
+        // all the windows of the first repeat come first
+        assert_eq!(
+            rows,
+            vec![
+                "test2\t20\t2\t0\tTTAGG",
+                "test2\t40\t0\t0\tTTAGG",
+                "test2\t52\t0\t0\tTTAGG",
+                "test2\t20\t2\t0\tTCAGG",
+                "test2\t40\t0\t0\tTCAGG",
+                "test2\t52\t0\t2\tTCAGG",
+            ]
+        );
+
+        // a bedgraph has one line for each window, with every repeat summed
+        let mut out = Vec::new();
+        write_window_counts(
+            &rec,
+            &mut out,
+            &["TTAGG", "TCAGG"],
+            0,
+            None,
+            &params(20, 20, Tolerance::Exact, OutputFormat::Bedgraph),
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "test2\t0\t20\t4\ntest2\t20\t40\t0\ntest2\t40\t52\t2\n"
+        );
+    }
+}